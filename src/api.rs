@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::{error::Error, time::Duration};
 use tokio::sync::mpsc::error::TryRecvError;
 
@@ -9,25 +10,43 @@ use ratatui::Terminal;
 use ratatui::backend::CrosstermBackend;
 use std::io::Stdout;
 
-use crate::tui::{App, AppEvent, ui};
+use crate::history;
+use crate::tools;
+use crate::tui::{App, AppEvent, ChatMessage, ui};
+
+/// 防止模型反复调用工具导致死循环，超过此轮数后放弃继续自动执行。
+const MAX_TOOL_ITERATIONS: u32 = 8;
+
+/// 这些命令只会排队等待 Ctrl+Y/Ctrl+N 确认，并不会真正触碰磁盘；
+/// 模型看到的「已生成待确认操作」不是「已完成」，因此遇到它们后必须停止自动循环，
+/// 否则模型会把「已排队」误当成「已完成」继续发出后续工具调用。
+const GATED_COMMANDS: &[&str] = &["rm", "write", "move-content", "edit-at"];
+
+/// 流式响应过程中逐步拼接出的一次未完成工具调用。
+#[derive(Default, Clone)]
+struct PendingToolCall {
+    id: String,
+    name: String,
+    arguments: String,
+}
 
 pub async fn stream_to_openai(
     app: &mut App,
     terminal: &mut Terminal<CrosstermBackend<Stdout>>,
 ) -> Result<(), Box<dyn Error>> {
     // Build messages in OpenAI format（包含最新的 user 输入）
-    let msgs: Vec<ChatCompletionMessage> = app
+    let mut msgs: Vec<ChatCompletionMessage> = app
         .messages
         .iter()
-        .map(|(role, content)| {
-            let role_enum = match role.as_str() {
+        .map(|msg| {
+            let role_enum = match msg.role.as_str() {
                 "system" => ChatCompletionMessageRole::System,
                 "assistant" => ChatCompletionMessageRole::Assistant,
                 _ => ChatCompletionMessageRole::User,
             };
             ChatCompletionMessage {
                 role: role_enum,
-                content: Some(content.clone()),
+                content: Some(msg.content.clone()),
                 name: None,
                 function_call: None,
                 tool_calls: None,
@@ -36,33 +55,187 @@ pub async fn stream_to_openai(
         })
         .collect();
 
-    // 创建流
+    let tools = tools::tool_schemas();
+
+    for _ in 0..MAX_TOOL_ITERATIONS {
+        let tool_calls = stream_one_turn(app, terminal, &mut msgs, &tools).await?;
+        if tool_calls.is_empty() {
+            break;
+        }
+
+        let mut awaiting_confirmation = false;
+        for call in tool_calls {
+            app.messages.push(ChatMessage::new(
+                "tool_call",
+                format!("调用工具 {}({})", call.name, call.arguments),
+            ));
+            terminal.draw(|f| ui(f, app))?;
+            let result = match tools::command_from_tool_call(&call.name, &call.arguments) {
+                Some(cmd) => cmd
+                    .execute(app.events_tx.clone())
+                    .await
+                    .unwrap_or_else(|e| format!("工具调用失败: {}", e)),
+                None => format!("未知工具或参数无效: {}", call.name),
+            };
+            app.messages.push(ChatMessage::new(
+                "tool",
+                format!("[{}] {}", call.name, result),
+            ));
+            if GATED_COMMANDS.contains(&call.name.as_str()) {
+                awaiting_confirmation = true;
+            }
+            msgs.push(ChatCompletionMessage {
+                role: ChatCompletionMessageRole::Tool,
+                content: Some(result),
+                name: None,
+                function_call: None,
+                tool_calls: None,
+                tool_call_id: Some(call.id),
+            });
+            terminal.draw(|f| ui(f, app))?;
+        }
+
+        if awaiting_confirmation {
+            // 这些命令尚未真正执行，只是排队等待人工确认；在用户按下 Ctrl+Y/Ctrl+N 之前，
+            // 绝不能让模型以为它已经完成并继续自动调用后续工具。
+            app.messages.push(ChatMessage::new(
+                "system",
+                "存在待确认的破坏性操作，已暂停自动工具调用循环；请按 Ctrl+Y 应用或 Ctrl+N 丢弃后再继续对话".to_string(),
+            ));
+            terminal.draw(|f| ui(f, app))?;
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// 发起一轮流式请求，边收边显示助手文本，并返回本轮模型请求的工具调用（若有）。
+async fn stream_one_turn(
+    app: &mut App,
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    msgs: &mut Vec<ChatCompletionMessage>,
+    tools: &[serde_json::Value],
+) -> Result<Vec<PendingToolCall>, Box<dyn Error>> {
     let mut chat_stream = ChatCompletionDelta::builder(&app.model, msgs.clone())
+        .tools(tools.to_vec())
         .create_stream()
         .await?;
 
     // 追加占位的 assistant 消息，用于边收边显示
-    app.messages.push(("assistant".to_string(), String::new()));
+    app.messages.push(ChatMessage::new("assistant", String::new()));
     let idx = app.messages.len() - 1;
 
     let mut merged: Option<ChatCompletionDelta> = None;
+    // 按 tool_calls 的 index 缓冲分片到达的 id/name/arguments
+    let mut pending: BTreeMap<usize, PendingToolCall> = BTreeMap::new();
     loop {
         // 消费后台事件（文件工具等），保持 UI 响应与动画
         while let Ok(ev) = app.events_rx.try_recv() {
             match ev {
                 AppEvent::Status(s) => app.status = s,
-                AppEvent::System(m) => app.messages.push(("system".to_string(), m)),
+                AppEvent::System(m) => app.messages.push(ChatMessage::new("system", m)),
+                AppEvent::Edit(edit) => app.pending_edits.push(edit),
+                AppEvent::SaveRequested(name) => {
+                    let result = crate::session::save(&name, &app.model, &app.messages).await;
+                    let msg = match result {
+                        Ok(()) => format!("已保存会话 '{}'", name),
+                        Err(e) => format!("保存会话失败: {}", e),
+                    };
+                    app.messages.push(ChatMessage::new("system", msg));
+                }
+                AppEvent::LoadRequested(name) => match crate::session::load(&name).await {
+                    Ok(data) => {
+                        app.model = data.model;
+                        app.messages = data.messages;
+                        app.messages
+                            .push(ChatMessage::new("system", format!("已加载会话 '{}'", name)));
+                    }
+                    Err(e) => app.messages.push(ChatMessage::new(
+                        "system",
+                        format!("加载会话失败: {}", e),
+                    )),
+                },
+                AppEvent::ProcessStarted(child) => app.running_child = Some(child),
+                AppEvent::ProcessFinished(_) => app.running_child = None,
+                AppEvent::HistoryRecord(transaction) => app.history.record(transaction),
+                AppEvent::ConfirmRequested(action) => app.pending_confirmations.push(action),
+                AppEvent::UndoRequested => {
+                    let msg = match app.history.undo() {
+                        Some(t) => match history::apply(&t).await {
+                            Ok(()) => format!("已撤销 {} 处文件变更", t.changes.len()),
+                            Err(e) => format!("撤销失败: {}", e),
+                        },
+                        None => "已到最早状态，无法继续撤销".to_string(),
+                    };
+                    app.messages.push(ChatMessage::new("system", msg));
+                }
+                AppEvent::RedoRequested => {
+                    let msg = match app.history.redo() {
+                        Some(t) => match history::apply(&t).await {
+                            Ok(()) => format!("已重做 {} 处文件变更", t.changes.len()),
+                            Err(e) => format!("重做失败: {}", e),
+                        },
+                        None => "没有可重做的变更".to_string(),
+                    };
+                    app.messages.push(ChatMessage::new("system", msg));
+                }
+                AppEvent::EarlierRequested(kind) => {
+                    let transactions = app.history.earlier(kind);
+                    for t in &transactions {
+                        history::apply(t).await.ok();
+                    }
+                    app.messages.push(ChatMessage::new(
+                        "system",
+                        format!("已回溯 {} 次修订", transactions.len()),
+                    ));
+                }
+                AppEvent::LaterRequested(kind) => {
+                    let transactions = app.history.later(kind);
+                    for t in &transactions {
+                        history::apply(t).await.ok();
+                    }
+                    app.messages.push(ChatMessage::new(
+                        "system",
+                        format!("已前进 {} 次修订", transactions.len()),
+                    ));
+                }
+                AppEvent::Token(chunk) => {
+                    if let Some(last) = app.messages.last_mut() {
+                        last.content.push_str(&chunk);
+                    }
+                }
+                AppEvent::CommandOutput { label, body, folded } => {
+                    app.messages.push(crate::tui::fold_command_output(label, body, folded));
+                }
             }
             terminal.draw(|f| ui(f, app))?;
         }
         match chat_stream.try_recv() {
             Ok(delta) => {
                 if let Some(content) = &delta.choices[0].delta.content {
-                    app.messages[idx].1.push_str(content);
+                    app.messages[idx].content.push_str(content);
                     // 每收到一段内容就重绘
                     terminal.draw(|f| ui(f, app))?;
                 }
 
+                if let Some(deltas) = &delta.choices[0].delta.tool_calls {
+                    for tc in deltas {
+                        let entry = pending.entry(tc.index).or_default();
+                        if let Some(id) = &tc.id {
+                            entry.id = id.clone();
+                        }
+                        if let Some(function) = &tc.function {
+                            if let Some(name) = &function.name {
+                                entry.name.push_str(name);
+                            }
+                            if let Some(args) = &function.arguments {
+                                entry.arguments.push_str(args);
+                            }
+                        }
+                    }
+                }
+
                 if let Some(m) = merged.as_mut() {
                     // 合并增量
                     m.merge(delta)?;
@@ -77,7 +250,48 @@ pub async fn stream_to_openai(
         }
     }
 
+    // 流未产生任何增量（例如后端立即断开/返回空响应）时没有内容可合并，
+    // 直接报错而不是 panic，交由上层以 Status::Error 展示。
+    let Some(merged) = merged else {
+        return Err("模型未返回任何内容，请检查网络或重试".into());
+    };
     // 可选：将最终合并结果转为完整 ChatCompletion（当前未使用）
-    let _final_completion: ChatCompletion = merged.unwrap().into();
-    Ok(())
+    let _final_completion: ChatCompletion = merged.into();
+
+    let assistant_tool_calls: Vec<PendingToolCall> = pending.into_values().collect();
+    if !assistant_tool_calls.is_empty() {
+        msgs.push(ChatCompletionMessage {
+            role: ChatCompletionMessageRole::Assistant,
+            content: None,
+            name: None,
+            function_call: None,
+            tool_calls: Some(
+                assistant_tool_calls
+                    .iter()
+                    .enumerate()
+                    .map(|(index, call)| openai::chat::ChatCompletionMessageToolCall {
+                        index: Some(index),
+                        id: call.id.clone(),
+                        r#type: "function".to_string(),
+                        function: openai::chat::ChatCompletionFunctionCall {
+                            name: call.name.clone(),
+                            arguments: call.arguments.clone(),
+                        },
+                    })
+                    .collect(),
+            ),
+            tool_call_id: None,
+        });
+    } else {
+        msgs.push(ChatCompletionMessage {
+            role: ChatCompletionMessageRole::Assistant,
+            content: Some(app.messages[idx].content.clone()),
+            name: None,
+            function_call: None,
+            tool_calls: None,
+            tool_call_id: None,
+        });
+    }
+
+    Ok(assistant_tool_calls)
 }
@@ -0,0 +1,21 @@
+use std::error::Error;
+use tokio::fs;
+
+/// 与 `FindCommand` 相同的栈式遍历，收集当前工作目录下所有文件的相对路径，供补全使用。
+pub async fn collect_paths() -> Result<Vec<String>, Box<dyn Error>> {
+    let cwd = std::env::current_dir()?;
+    let mut found = Vec::new();
+    let mut stack = vec![cwd.clone()];
+    while let Some(dir) = stack.pop() {
+        let mut rd = fs::read_dir(&dir).await?;
+        while let Some(ent) = rd.next_entry().await? {
+            let p = ent.path();
+            if p.is_dir() {
+                stack.push(p);
+            } else if let Ok(rel) = p.strip_prefix(&cwd) {
+                found.push(rel.display().to_string());
+            }
+        }
+    }
+    Ok(found)
+}
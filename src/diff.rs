@@ -0,0 +1,108 @@
+/// 统一 diff 视图中的一行：保留的上下文行、新增行或删除行。
+#[derive(Clone)]
+pub enum DiffOp {
+    Context(String),
+    Added(String),
+    Removed(String),
+}
+
+/// 一次尚未写入磁盘、等待用户确认的文件变更。
+pub struct PendingEdit {
+    pub path: String,
+    /// 应用前的文件内容，`None` 表示文件原本不存在；用于确认后记录撤销历史。
+    pub old_content: Option<String>,
+    pub new_content: String,
+    pub diff: Vec<DiffOp>,
+}
+
+/// 基于最长公共子序列的逐行 diff（与 `git diff` 思路一致的教科书实现）。
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffOp> {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let n = old_lines.len();
+    let m = new_lines.len();
+
+    let mut dp = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            dp[i][j] = if old_lines[i] == new_lines[j] {
+                dp[i + 1][j + 1] + 1
+            } else {
+                dp[i + 1][j].max(dp[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old_lines[i] == new_lines[j] {
+            ops.push(DiffOp::Context(old_lines[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if dp[i + 1][j] >= dp[i][j + 1] {
+            ops.push(DiffOp::Removed(old_lines[i].to_string()));
+            i += 1;
+        } else {
+            ops.push(DiffOp::Added(new_lines[j].to_string()));
+            j += 1;
+        }
+    }
+    while i < n {
+        ops.push(DiffOp::Removed(old_lines[i].to_string()));
+        i += 1;
+    }
+    while j < m {
+        ops.push(DiffOp::Added(new_lines[j].to_string()));
+        j += 1;
+    }
+    ops
+}
+
+/// 等待用户按 Ctrl+Y/Ctrl+N 确认或丢弃的破坏性操作；确认前磁盘不会被实际修改。
+pub enum PendingConfirmation {
+    /// 删除 `path`。
+    Remove { path: String },
+    /// 将 `src` 的 `[start_line, end_line]` 行移动到 `dst` 的 `dst_line` 行前。
+    MoveContent {
+        src: String,
+        start_line: usize,
+        end_line: usize,
+        dst: String,
+        dst_line: usize,
+    },
+}
+
+impl PendingConfirmation {
+    /// 供 TUI 展示的一行摘要。
+    pub fn describe(&self) -> String {
+        match self {
+            PendingConfirmation::Remove { path } => format!("rm {}", path),
+            PendingConfirmation::MoveContent {
+                src,
+                start_line,
+                end_line,
+                dst,
+                dst_line,
+            } => format!(
+                "move-content {}:{}-{} -> {}:{}",
+                src, start_line, end_line, dst, dst_line
+            ),
+        }
+    }
+}
+
+/// 构造一个待确认编辑：计算旧/新内容的 diff 并打包。
+pub fn build_pending_edit(
+    path: String,
+    old_content: Option<&str>,
+    new_content: String,
+) -> PendingEdit {
+    let diff = diff_lines(old_content.unwrap_or(""), &new_content);
+    PendingEdit {
+        path,
+        old_content: old_content.map(|s| s.to_string()),
+        new_content,
+        diff,
+    }
+}
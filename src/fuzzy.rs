@@ -0,0 +1,115 @@
+/// 对 `candidate` 按 `query` 做子序列模糊匹配打分。
+///
+/// 命中需要按 `query` 的字符顺序出现在 `candidate` 中（子序列），否则返回 `None`。
+/// 连续命中、路径分隔符/驼峰边界后的命中、以及从 basename 开头命中都会获得加分，
+/// 跨度越短分数越高，因此越紧凑、越贴近单词边界的匹配排名越靠前。
+pub fn fuzzy_match(query: &str, candidate: &str) -> Option<(i64, Vec<usize>)> {
+    if query.is_empty() {
+        return Some((0, Vec::new()));
+    }
+
+    let query_lower: Vec<char> = query.to_lowercase().chars().collect();
+    let chars: Vec<char> = candidate.chars().collect();
+    let chars_lower: Vec<char> = candidate.to_lowercase().chars().collect();
+
+    let mut positions = Vec::with_capacity(query_lower.len());
+    let mut score: i64 = 0;
+    let mut last_match: Option<usize> = None;
+    let mut qi = 0;
+
+    for (ci, ch) in chars_lower.iter().enumerate() {
+        if qi >= query_lower.len() {
+            break;
+        }
+        if *ch != query_lower[qi] {
+            continue;
+        }
+
+        let mut bonus = 1;
+        if last_match == Some(ci.wrapping_sub(1)) {
+            bonus += 5; // 连续命中
+        }
+        let is_boundary = ci == 0
+            || matches!(chars[ci - 1], '/' | '\\' | '_' | '-' | '.')
+            || (chars[ci].is_uppercase() && !chars[ci - 1].is_uppercase());
+        if is_boundary {
+            bonus += 3;
+        }
+
+        score += bonus;
+        positions.push(ci);
+        last_match = Some(ci);
+        qi += 1;
+    }
+
+    if qi < query_lower.len() {
+        return None; // query 不是 candidate 的子序列
+    }
+
+    let basename_start = candidate.rfind(['/', '\\']).map(|i| i + 1).unwrap_or(0);
+    if positions.first() == Some(&basename_start) {
+        score += 10;
+    }
+
+    if let (Some(&first), Some(&last)) = (positions.first(), positions.last()) {
+        score -= ((last - first) as i64) / 2; // 跨度越大扣分越多
+    }
+
+    Some((score, positions))
+}
+
+/// 按分数从高到低对候选集排序，丢弃不匹配的候选。
+pub fn rank_candidates(query: &str, candidates: &[String]) -> Vec<(String, i64, Vec<usize>)> {
+    let mut scored: Vec<(String, i64, Vec<usize>)> = candidates
+        .iter()
+        .filter_map(|c| fuzzy_match(query, c).map(|(score, positions)| (c.clone(), score, positions)))
+        .collect();
+    scored.sort_by(|a, b| b.1.cmp(&a.1));
+    scored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_ordered_subsequence() {
+        assert!(fuzzy_match("srvmn", "server/main.rs").is_some());
+    }
+
+    #[test]
+    fn rejects_out_of_order_characters() {
+        assert!(fuzzy_match("nmrvs", "server/main.rs").is_none());
+    }
+
+    #[test]
+    fn empty_query_matches_everything_with_zero_score() {
+        assert_eq!(fuzzy_match("", "anything"), Some((0, Vec::new())));
+    }
+
+    #[test]
+    fn basename_start_scores_higher_than_mid_path_match() {
+        let (basename_score, _) = fuzzy_match("main", "src/main.rs").unwrap();
+        let (mid_score, _) = fuzzy_match("main", "src/domain.rs").unwrap();
+        assert!(basename_score > mid_score);
+    }
+
+    #[test]
+    fn consecutive_hits_score_higher_than_scattered_hits() {
+        let (consecutive, _) = fuzzy_match("main", "main.rs").unwrap();
+        let (scattered, _) = fuzzy_match("main", "m_a_i_n.rs").unwrap();
+        assert!(consecutive > scattered);
+    }
+
+    #[test]
+    fn rank_candidates_sorts_descending_and_drops_non_matches() {
+        let candidates = vec![
+            "server/main.rs".to_string(),
+            "server/network.rs".to_string(),
+            "unrelated.txt".to_string(),
+        ];
+        let ranked = rank_candidates("srvmn", &candidates);
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].0, "server/main.rs");
+    }
+}
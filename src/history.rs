@@ -0,0 +1,269 @@
+use std::time::{Duration, SystemTime};
+
+/// 单个文件在一次变更前后的内容快照。
+/// `None` 表示文件在对应时间点不存在（`old_content: None` 为新建，`new_content: None` 为删除）。
+#[derive(Clone)]
+pub struct FileChange {
+    pub path: String,
+    pub old_content: Option<String>,
+    pub new_content: Option<String>,
+}
+
+impl FileChange {
+    fn inverted(&self) -> Self {
+        Self {
+            path: self.path.clone(),
+            old_content: self.new_content.clone(),
+            new_content: self.old_content.clone(),
+        }
+    }
+}
+
+/// 一次命令产生的变更集合；`move-content` 等跨文件操作会在一个事务里携带多个 `FileChange`，
+/// 保证撤销时整体生效（而不是只回滚其中一个文件）。
+#[derive(Clone)]
+pub struct Transaction {
+    pub changes: Vec<FileChange>,
+}
+
+impl Transaction {
+    pub fn invert(&self) -> Transaction {
+        Transaction {
+            changes: self.changes.iter().map(FileChange::inverted).collect(),
+        }
+    }
+}
+
+/// 将一个事务实际写入磁盘：`new_content` 为 `Some` 时写入，`None` 时删除对应文件。
+pub async fn apply(transaction: &Transaction) -> std::io::Result<()> {
+    for change in &transaction.changes {
+        match &change.new_content {
+            Some(content) => tokio::fs::write(&change.path, content).await?,
+            None => match tokio::fs::remove_file(&change.path).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            },
+        }
+    }
+    Ok(())
+}
+
+/// 历史树中的一次修订：记录产生它的事务、父修订下标与时间戳。
+struct Revision {
+    parent: Option<usize>,
+    children: Vec<usize>,
+    transaction: Transaction,
+    timestamp: SystemTime,
+}
+
+/// `:earlier`/`:later` 的步进单位：按变更次数或按时间跨度回溯/前进。
+#[derive(Clone, Copy)]
+pub enum UndoKind {
+    Steps(usize),
+    TimeSpan(Duration),
+}
+
+/// 解析形如 `5m`（5 分钟）、`2c`（2 次变更）的 Helix 风格参数。
+pub fn parse_undo_kind(spec: &str) -> Option<UndoKind> {
+    let spec = spec.trim();
+    if spec.len() < 2 {
+        return None;
+    }
+    let (num_part, suffix) = spec.split_at(spec.len() - 1);
+    let n: u64 = num_part.parse().ok()?;
+    match suffix {
+        "c" => Some(UndoKind::Steps(n as usize)),
+        "s" => Some(UndoKind::TimeSpan(Duration::from_secs(n))),
+        "m" => Some(UndoKind::TimeSpan(Duration::from_secs(n * 60))),
+        "h" => Some(UndoKind::TimeSpan(Duration::from_secs(n * 3600))),
+        "d" => Some(UndoKind::TimeSpan(Duration::from_secs(n * 86400))),
+        _ => None,
+    }
+}
+
+/// Helix 风格的修订树：每次命令产生的事务都作为 `current` 的新子节点入树，
+/// `:undo`/`:redo` 沿父子边移动 `current`，历史本身永不丢失（旧分支的修订仍留在树中，但 `:redo` 只沿最近创建的子修订前进）。
+pub struct History {
+    revisions: Vec<Revision>,
+    current: usize,
+}
+
+impl History {
+    pub fn new() -> Self {
+        let root = Revision {
+            parent: None,
+            children: Vec::new(),
+            transaction: Transaction { changes: Vec::new() },
+            timestamp: SystemTime::now(),
+        };
+        Self {
+            revisions: vec![root],
+            current: 0,
+        }
+    }
+
+    /// 将一次已发生的变更记录为 `current` 的新子修订，并将 `current` 移到它上面。
+    pub fn record(&mut self, transaction: Transaction) {
+        let parent = self.current;
+        let idx = self.revisions.len();
+        self.revisions.push(Revision {
+            parent: Some(parent),
+            children: Vec::new(),
+            transaction,
+            timestamp: SystemTime::now(),
+        });
+        self.revisions[parent].children.push(idx);
+        self.current = idx;
+    }
+
+    /// 返回 `current` 修订的逆事务（由调用方写盘）并将 `current` 移到其父节点；位于根节点时返回 `None`。
+    pub fn undo(&mut self) -> Option<Transaction> {
+        let cur = &self.revisions[self.current];
+        let parent = cur.parent?;
+        let inverse = cur.transaction.invert();
+        self.current = parent;
+        Some(inverse)
+    }
+
+    /// 跟随 `current` 最近一次创建的子修订前进，返回其正向事务供调用方重放。
+    pub fn redo(&mut self) -> Option<Transaction> {
+        let next = *self.revisions[self.current].children.last()?;
+        let transaction = self.revisions[next].transaction.clone();
+        self.current = next;
+        Some(transaction)
+    }
+
+    /// 按步数或时间跨度连续撤销，返回依次应用的逆事务（已按撤销顺序排列）。
+    pub fn earlier(&mut self, kind: UndoKind) -> Vec<Transaction> {
+        let mut applied = Vec::new();
+        match kind {
+            UndoKind::Steps(n) => {
+                for _ in 0..n {
+                    match self.undo() {
+                        Some(t) => applied.push(t),
+                        None => break,
+                    }
+                }
+            }
+            UndoKind::TimeSpan(span) => {
+                let cutoff = SystemTime::now()
+                    .checked_sub(span)
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+                while self.current != 0 && self.revisions[self.current].timestamp >= cutoff {
+                    match self.undo() {
+                        Some(t) => applied.push(t),
+                        None => break,
+                    }
+                }
+            }
+        }
+        applied
+    }
+
+    /// 按步数或时间跨度连续重做，返回依次应用的正向事务。
+    pub fn later(&mut self, kind: UndoKind) -> Vec<Transaction> {
+        let mut applied = Vec::new();
+        match kind {
+            UndoKind::Steps(n) => {
+                for _ in 0..n {
+                    match self.redo() {
+                        Some(t) => applied.push(t),
+                        None => break,
+                    }
+                }
+            }
+            UndoKind::TimeSpan(span) => {
+                let cutoff = SystemTime::now()
+                    .checked_sub(span)
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+                loop {
+                    let Some(&next) = self.revisions[self.current].children.last() else {
+                        break;
+                    };
+                    if self.revisions[next].timestamp < cutoff {
+                        break;
+                    }
+                    match self.redo() {
+                        Some(t) => applied.push(t),
+                        None => break,
+                    }
+                }
+            }
+        }
+        applied
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(path: &str, old: Option<&str>, new: Option<&str>) -> FileChange {
+        FileChange {
+            path: path.to_string(),
+            old_content: old.map(str::to_string),
+            new_content: new.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn undo_then_redo_round_trips() {
+        let mut history = History::new();
+        history.record(Transaction {
+            changes: vec![change("a.txt", Some("old"), Some("new"))],
+        });
+
+        let inverse = history.undo().expect("root has a child revision to undo");
+        assert_eq!(inverse.changes[0].new_content.as_deref(), Some("old"));
+
+        let forward = history.redo().expect("undo left a child revision to redo");
+        assert_eq!(forward.changes[0].new_content.as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn undo_at_root_is_a_no_op() {
+        let mut history = History::new();
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn move_content_transaction_inverts_both_files_atomically() {
+        let transaction = Transaction {
+            changes: vec![
+                change("src.txt", Some("a\nb\n"), Some("b\n")),
+                change("dst.txt", Some("c\n"), Some("a\nc\n")),
+            ],
+        };
+        let inverse = transaction.invert();
+        assert_eq!(inverse.changes[0].new_content.as_deref(), Some("a\nb\n"));
+        assert_eq!(inverse.changes[1].new_content.as_deref(), Some("c\n"));
+    }
+
+    #[test]
+    fn earlier_by_steps_stops_at_root() {
+        let mut history = History::new();
+        history.record(Transaction {
+            changes: vec![change("a.txt", None, Some("1"))],
+        });
+        let applied = history.earlier(UndoKind::Steps(5));
+        assert_eq!(applied.len(), 1);
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn parse_undo_kind_accepts_steps_and_time_spans() {
+        assert!(matches!(parse_undo_kind("3c"), Some(UndoKind::Steps(3))));
+        assert!(matches!(
+            parse_undo_kind("5m"),
+            Some(UndoKind::TimeSpan(d)) if d == Duration::from_secs(300)
+        ));
+        assert!(parse_undo_kind("").is_none());
+    }
+}
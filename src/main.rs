@@ -1,529 +1,23 @@
 use std::{error::Error, io, time::Duration};
 
-use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
 use crossterm::{execute, terminal::EnterAlternateScreen, terminal::LeaveAlternateScreen};
-use ratatui::prelude::*;
-use ratatui::widgets::{Block, Borders, Paragraph};
 
-use openai::chat::{
-    ChatCompletion, ChatCompletionDelta, ChatCompletionMessage, ChatCompletionMessageRole,
-};
-use std::future::Future;
-use std::pin::Pin;
-use tokio::sync::mpsc::error::TryRecvError;
-use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
-
-enum Status {
-    Idle,
-    Requesting,
-    Error(String),
-}
-
-enum AppEvent {
-    Status(Status),
-    System(String),
-}
-
-type CmdResult = Result<String, Box<dyn Error>>;
-type CmdFuture = Pin<Box<dyn Future<Output = CmdResult> + Send>>;
-
-trait ToolCommand: Send {
-    fn execute(&self) -> CmdFuture;
-}
-
-struct TouchCommand {
-    path: String,
-}
-
-impl ToolCommand for TouchCommand {
-    fn execute(&self) -> CmdFuture {
-        let path = self.path.clone();
-        Box::pin(async move {
-            use tokio::fs;
-            fs::write(path, "").await?;
-            Ok("文件已创建".to_string())
-        })
-    }
-}
-
-struct RmCommand {
-    path: String,
-}
-
-impl ToolCommand for RmCommand {
-    fn execute(&self) -> CmdFuture {
-        let path = self.path.clone();
-        Box::pin(async move {
-            use tokio::fs;
-            fs::remove_file(path).await?;
-            Ok("文件已删除".to_string())
-        })
-    }
-}
-
-struct WriteCommand {
-    path: String,
-    content: String,
-}
-
-impl ToolCommand for WriteCommand {
-    fn execute(&self) -> CmdFuture {
-        let path = self.path.clone();
-        let content = self.content.clone();
-        Box::pin(async move {
-            use tokio::fs;
-            fs::write(path, content).await?;
-            Ok("文件已写入".to_string())
-        })
-    }
-}
-
-struct FindCommand {
-    pattern: String,
-}
-
-impl ToolCommand for FindCommand {
-    fn execute(&self) -> CmdFuture {
-        let pattern = self.pattern.clone();
-        Box::pin(async move {
-            use tokio::fs;
-            let cwd = std::env::current_dir()?;
-            let mut found: Vec<String> = Vec::new();
-            let mut stack = vec![cwd];
-            while let Some(dir) = stack.pop() {
-                let mut rd = fs::read_dir(&dir).await?;
-                while let Some(ent) = rd.next_entry().await? {
-                    let p = ent.path();
-                    if p.is_dir() {
-                        stack.push(p);
-                    } else if let Some(name) = p.file_name().and_then(|s| s.to_str()) {
-                        if name.contains(&pattern) {
-                            found.push(p.display().to_string());
-                        }
-                    }
-                }
-            }
-            Ok(format!(
-                "匹配到 {} 个文件:\n{}",
-                found.len(),
-                found.join("\n")
-            ))
-        })
-    }
-}
-
-struct EditAtCommand {
-    path: String,
-    line: usize,
-    col: usize,
-    content: String,
-}
-
-impl ToolCommand for EditAtCommand {
-    fn execute(&self) -> CmdFuture {
-        let path = self.path.clone();
-        let line = self.line;
-        let col = self.col;
-        let content = self.content.clone();
-        Box::pin(async move {
-            use tokio::fs;
-            let mut text = fs::read_to_string(&path).await?;
-            let mut offset = 0usize;
-            for (i, l) in text.lines().enumerate() {
-                if i + 1 == line {
-                    offset += col.min(l.len());
-                    break;
-                }
-                offset += l.len() + 1; // +\n
-            }
-            text.insert_str(offset, &content);
-            fs::write(path, text).await?;
-            Ok("已定点插入内容".to_string())
-        })
-    }
-}
-
-struct MoveContentCommand {
-    src: String,
-    start_line: usize,
-    end_line: usize,
-    dst: String,
-    dst_line: usize,
-}
-
-impl ToolCommand for MoveContentCommand {
-    fn execute(&self) -> CmdFuture {
-        let src = self.src.clone();
-        let start_line = self.start_line;
-        let end_line = self.end_line;
-        let dst = self.dst.clone();
-        let dst_line = self.dst_line;
-        Box::pin(async move {
-            use tokio::fs;
-            let mut src_text = fs::read_to_string(&src).await?;
-            let dst_text = fs::read_to_string(&dst).await?;
-
-            let lines: Vec<&str> = src_text.lines().collect();
-            let start = start_line.saturating_sub(1);
-            let end = end_line.min(lines.len());
-            let moving = lines[start..end].join("\n");
-
-            // remove from src
-            let mut new_src = String::new();
-            for (i, l) in lines.iter().enumerate() {
-                if i < start || i >= end {
-                    new_src.push_str(l);
-                    new_src.push('\n');
-                }
-            }
-            src_text = new_src;
-
-            // insert into dst
-            let mut offset = 0usize;
-            for (i, l) in dst_text.lines().enumerate() {
-                if i + 1 == dst_line {
-                    break;
-                }
-                offset += l.len() + 1;
-            }
-            let mut dst_new = dst_text.clone();
-            dst_new.insert_str(offset, &format!("{}\n", moving));
-
-            fs::write(&src, src_text).await?;
-            fs::write(&dst, dst_new).await?;
-            Ok("已移动内容".to_string())
-        })
-    }
-}
-
-// 命令匹配与解析规格（将字符串匹配逻辑移动到 trait 中）
-trait CommandSpec {
-    fn name(&self) -> &'static str;
-    fn parse(&self, args: &str) -> Option<Box<dyn ToolCommand + Send>>;
-}
-
-struct TouchSpec;
-impl CommandSpec for TouchSpec {
-    fn name(&self) -> &'static str {
-        "touch"
-    }
-    fn parse(&self, args: &str) -> Option<Box<dyn ToolCommand + Send>> {
-        let mut parts = args.split_whitespace();
-        let path = parts.next()?;
-        Some(Box::new(TouchCommand {
-            path: path.to_string(),
-        }))
-    }
-}
-
-struct RmSpec;
-impl CommandSpec for RmSpec {
-    fn name(&self) -> &'static str {
-        "rm"
-    }
-    fn parse(&self, args: &str) -> Option<Box<dyn ToolCommand + Send>> {
-        let mut parts = args.split_whitespace();
-        let path = parts.next()?;
-        Some(Box::new(RmCommand {
-            path: path.to_string(),
-        }))
-    }
-}
-
-struct WriteSpec;
-impl CommandSpec for WriteSpec {
-    fn name(&self) -> &'static str {
-        "write"
-    }
-    fn parse(&self, args: &str) -> Option<Box<dyn ToolCommand + Send>> {
-        let mut it = args.splitn(2, ' ');
-        let path = it.next()?;
-        let content = it.next().unwrap_or("");
-        Some(Box::new(WriteCommand {
-            path: path.to_string(),
-            content: content.to_string(),
-        }))
-    }
-}
-
-struct FindSpec;
-impl CommandSpec for FindSpec {
-    fn name(&self) -> &'static str {
-        "find"
-    }
-    fn parse(&self, args: &str) -> Option<Box<dyn ToolCommand + Send>> {
-        let pattern = args.split_whitespace().next().unwrap_or("");
-        Some(Box::new(FindCommand {
-            pattern: pattern.to_string(),
-        }))
-    }
-}
-
-struct EditAtSpec;
-impl CommandSpec for EditAtSpec {
-    fn name(&self) -> &'static str {
-        "edit-at"
-    }
-    fn parse(&self, args: &str) -> Option<Box<dyn ToolCommand + Send>> {
-        let mut parts = args.split_whitespace();
-        let path = parts.next()?.to_string();
-        let line: usize = parts.next()?.parse().ok()?;
-        let col: usize = parts.next()?.parse().ok()?;
-        // 剩余内容作为待插入文本
-        let consumed = format!("{} {} {}", path, line, col);
-        let content = args
-            .strip_prefix(&consumed)
-            .and_then(|s| s.strip_prefix(' '))
-            .unwrap_or("")
-            .to_string();
-        Some(Box::new(EditAtCommand {
-            path,
-            line,
-            col,
-            content,
-        }))
-    }
-}
-
-struct MoveContentSpec;
-impl CommandSpec for MoveContentSpec {
-    fn name(&self) -> &'static str {
-        "move-content"
-    }
-    fn parse(&self, args: &str) -> Option<Box<dyn ToolCommand + Send>> {
-        let mut parts = args.split_whitespace();
-        let src = parts.next()?.to_string();
-        let start_line: usize = parts.next()?.parse().ok()?;
-        let end_line: usize = parts.next()?.parse().ok()?;
-        let dst = parts.next()?.to_string();
-        let dst_line: usize = parts.next()?.parse().ok()?;
-        Some(Box::new(MoveContentCommand {
-            src,
-            start_line,
-            end_line,
-            dst,
-            dst_line,
-        }))
-    }
-}
-
-fn command_specs() -> Vec<Box<dyn CommandSpec>> {
-    vec![
-        Box::new(TouchSpec),
-        Box::new(RmSpec),
-        Box::new(WriteSpec),
-        Box::new(FindSpec),
-        Box::new(EditAtSpec),
-        Box::new(MoveContentSpec),
-    ]
-}
-
-struct App {
-    input: String,
-    messages: Vec<(String, String)>, // (role, content)
-    model: String,
-    status: Status,
-    events_tx: UnboundedSender<AppEvent>,
-    events_rx: UnboundedReceiver<AppEvent>,
-}
-
-fn mask_api_key(key: &str) -> String {
-    if key.is_empty() {
-        return "(未设置)".to_string();
-    }
-    let len = key.len();
-    if len <= 6 {
-        return "*".repeat(len);
-    }
-    let prefix = &key[..3];
-    let suffix = &key[len - 3..];
-    format!("{}{}{}", prefix, "*".repeat(len - 6), suffix)
-}
-
-impl App {
-    fn new() -> Self {
-        let model = std::env::var("OPENAI_MODEL").unwrap_or_else(|_| "gpt-4o-mini".to_string());
-        let api_base = std::env::var("OPENAI_API_BASE")
-            .unwrap_or_else(|_| "https://api.openai.com/v1".to_string());
-        let api_key = std::env::var("OPENAI_API_KEY").unwrap_or_default();
-        let masked_key = mask_api_key(&api_key);
-
-        let messages = vec![
-            (
-                "system".to_string(),
-                "你是一个助理，帮助进行AI编码。".to_string(),
-            ),
-            (
-                "system".to_string(),
-                format!(
-                    "当前配置：api_base={}, model={}, api_key={}",
-                    api_base, model, masked_key
-                ),
-            ),
-        ];
-
-        let (events_tx, events_rx) = unbounded_channel();
-
-        Self {
-            input: String::new(),
-            messages,
-            model,
-            status: Status::Idle,
-            events_tx,
-            events_rx,
-        }
-    }
-}
-
-fn ui(frame: &mut Frame, app: &App) {
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints(
-            [
-                Constraint::Min(5),
-                Constraint::Length(1),
-                Constraint::Length(3),
-            ]
-            .as_ref(),
-        )
-        .split(frame.area());
-
-    // Render messages
-    let history_text = app
-        .messages
-        .iter()
-        .map(|(role, content)| format!("{}: {}", role, content))
-        .collect::<Vec<_>>()
-        .join("\n");
-    let history = Paragraph::new(history_text);
-    frame.render_widget(history, chunks[0]);
-
-    // Render status（输入框上方，无边框）
-    let status_text = match &app.status {
-        Status::Idle => "按 Enter 发送，Esc 退出".to_string(),
-        Status::Requesting => "正在请求OpenAI...".to_string(),
-        Status::Error(e) => format!("请求失败: {}", e),
-    };
-    let status = Paragraph::new(status_text);
-    frame.render_widget(status, chunks[1]);
-
-    // Render input（底部）
-    let input = Paragraph::new(app.input.as_str()).block(
-        Block::default()
-            .title("输入（Enter 发送，Esc 退出）")
-            .borders(Borders::ALL),
-    );
-    frame.render_widget(input, chunks[2]);
-}
-
-fn parse_command(input: &str) -> Option<Box<dyn ToolCommand + Send>> {
-    let trimmed = input.trim();
-    if !trimmed.starts_with(':') {
-        return None;
-    }
-    let rest = &trimmed[1..];
-    let mut it = rest.splitn(2, ' ');
-    let cmd = it.next()?;
-    let args = it.next().unwrap_or("");
-    for spec in command_specs() {
-        if spec.name() == cmd {
-            if let Some(c) = spec.parse(args) {
-                return Some(c);
-            }
-        }
-    }
-    None
-}
-
-async fn run_command(cmd: Box<dyn ToolCommand + Send>, tx: UnboundedSender<AppEvent>) {
-    tx.send(AppEvent::Status(Status::Requesting)).ok();
-    let result = cmd.execute().await;
-
-    match result {
-        Ok(msg) => {
-            tx.send(AppEvent::System(msg)).ok();
-            tx.send(AppEvent::Status(Status::Idle)).ok();
-        }
-        Err(e) => {
-            tx.send(AppEvent::System(format!("操作失败: {}", e))).ok();
-            tx.send(AppEvent::Status(Status::Error(e.to_string()))).ok();
-        }
-    }
-}
-
-// 已移除非流式响应函数，统一使用流式响应
-
-async fn stream_to_openai(
-    app: &mut App,
-    terminal: &mut ratatui::Terminal<ratatui::backend::CrosstermBackend<std::io::Stdout>>,
-) -> Result<(), Box<dyn Error>> {
-    // Build messages in OpenAI format（包含最新的 user 输入）
-    let msgs: Vec<ChatCompletionMessage> = app
-        .messages
-        .iter()
-        .map(|(role, content)| {
-            let role_enum = match role.as_str() {
-                "system" => ChatCompletionMessageRole::System,
-                "assistant" => ChatCompletionMessageRole::Assistant,
-                _ => ChatCompletionMessageRole::User,
-            };
-            ChatCompletionMessage {
-                role: role_enum,
-                content: Some(content.clone()),
-                name: None,
-                function_call: None,
-                tool_calls: None,
-                tool_call_id: None,
-            }
-        })
-        .collect();
-
-    // 创建流
-    let mut chat_stream = ChatCompletionDelta::builder(&app.model, msgs.clone())
-        .create_stream()
-        .await?;
-
-    // 追加占位的 assistant 消息，用于边收边显示
-    app.messages.push(("assistant".to_string(), String::new()));
-    let idx = app.messages.len() - 1;
-
-    let mut merged: Option<ChatCompletionDelta> = None;
-    loop {
-        // 消费后台事件（文件工具等），保持 UI 响应与动画
-        while let Ok(ev) = app.events_rx.try_recv() {
-            match ev {
-                AppEvent::Status(s) => app.status = s,
-                AppEvent::System(m) => app.messages.push(("system".to_string(), m)),
-            }
-            terminal.draw(|f| ui(f, app))?;
-        }
-        match chat_stream.try_recv() {
-            Ok(delta) => {
-                if let Some(content) = &delta.choices[0].delta.content {
-                    app.messages[idx].1.push_str(content);
-                    // 每收到一段内容就重绘
-                    terminal.draw(|f| ui(f, app))?;
-                }
-
-                if let Some(m) = merged.as_mut() {
-                    // 合并增量
-                    m.merge(delta)?;
-                } else {
-                    merged = Some(delta);
-                }
-            }
-            Err(TryRecvError::Empty) => {
-                tokio::time::sleep(Duration::from_millis(50)).await;
-            }
-            Err(TryRecvError::Disconnected) => break,
-        }
-    }
-
-    // 可选：将最终合并结果转为完整 ChatCompletion（当前未使用）
-    let _final_completion: ChatCompletion = merged.unwrap().into();
-    Ok(())
-}
+mod api;
+mod complete;
+mod diff;
+mod fuzzy;
+mod history;
+mod providers;
+mod search;
+mod session;
+mod tools;
+mod tui;
+
+use api::stream_to_openai;
+use tools::{parse_command, run_command};
+use tui::{App, AppEvent, ChatMessage, PathPicker, fold_command_output, ui};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -545,7 +39,95 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     let mut app = App::new();
 
+    if let Some(name) = session::most_recent().await {
+        app.messages.push(ChatMessage::new(
+            "system",
+            format!("检测到最近的会话 '{}'，输入 :load {} 可继续", name, name),
+        ));
+    }
+
     loop {
+        // 消费后台事件（文件工具、待确认编辑等），不依赖正在进行的聊天流
+        while let Ok(ev) = app.events_rx.try_recv() {
+            match ev {
+                AppEvent::Status(s) => app.status = s,
+                AppEvent::System(m) => app.messages.push(ChatMessage::new("system", m)),
+                AppEvent::Edit(pending) => app.pending_edits.push(pending),
+                AppEvent::SaveRequested(name) => {
+                    let result = session::save(&name, &app.model, &app.messages).await;
+                    let msg = match result {
+                        Ok(()) => format!("已保存会话 '{}'", name),
+                        Err(e) => format!("保存会话失败: {}", e),
+                    };
+                    app.messages.push(ChatMessage::new("system", msg));
+                }
+                AppEvent::LoadRequested(name) => match session::load(&name).await {
+                    Ok(data) => {
+                        app.model = data.model;
+                        app.messages = data.messages;
+                        app.messages
+                            .push(ChatMessage::new("system", format!("已加载会话 '{}'", name)));
+                    }
+                    Err(e) => app.messages.push(ChatMessage::new(
+                        "system",
+                        format!("加载会话失败: {}", e),
+                    )),
+                },
+                AppEvent::ProcessStarted(child) => app.running_child = Some(child),
+                AppEvent::ProcessFinished(_) => app.running_child = None,
+                AppEvent::HistoryRecord(transaction) => app.history.record(transaction),
+                AppEvent::ConfirmRequested(action) => app.pending_confirmations.push(action),
+                AppEvent::UndoRequested => {
+                    let msg = match app.history.undo() {
+                        Some(t) => match history::apply(&t).await {
+                            Ok(()) => format!("已撤销 {} 处文件变更", t.changes.len()),
+                            Err(e) => format!("撤销失败: {}", e),
+                        },
+                        None => "已到最早状态，无法继续撤销".to_string(),
+                    };
+                    app.messages.push(ChatMessage::new("system", msg));
+                }
+                AppEvent::RedoRequested => {
+                    let msg = match app.history.redo() {
+                        Some(t) => match history::apply(&t).await {
+                            Ok(()) => format!("已重做 {} 处文件变更", t.changes.len()),
+                            Err(e) => format!("重做失败: {}", e),
+                        },
+                        None => "没有可重做的变更".to_string(),
+                    };
+                    app.messages.push(ChatMessage::new("system", msg));
+                }
+                AppEvent::EarlierRequested(kind) => {
+                    let transactions = app.history.earlier(kind);
+                    for t in &transactions {
+                        history::apply(t).await.ok();
+                    }
+                    app.messages.push(ChatMessage::new(
+                        "system",
+                        format!("已回溯 {} 次修订", transactions.len()),
+                    ));
+                }
+                AppEvent::LaterRequested(kind) => {
+                    let transactions = app.history.later(kind);
+                    for t in &transactions {
+                        history::apply(t).await.ok();
+                    }
+                    app.messages.push(ChatMessage::new(
+                        "system",
+                        format!("已前进 {} 次修订", transactions.len()),
+                    ));
+                }
+                AppEvent::Token(chunk) => {
+                    if let Some(last) = app.messages.last_mut() {
+                        last.content.push_str(&chunk);
+                    }
+                }
+                AppEvent::CommandOutput { label, body, folded } => {
+                    app.messages.push(fold_command_output(label, body, folded));
+                }
+            }
+        }
+
         terminal.draw(|f| ui(f, &app))?;
 
         if !event::poll(Duration::from_millis(200))? {
@@ -559,26 +141,186 @@ async fn main() -> Result<(), Box<dyn Error>> {
             continue;
         }
 
+        if key.modifiers.contains(KeyModifiers::CONTROL) {
+            match key.code {
+                KeyCode::Char('y') => {
+                    if let Some(edit) = app.pending_edits.pop() {
+                        match tokio::fs::write(&edit.path, &edit.new_content).await {
+                            Ok(()) => {
+                                app.history.record(history::Transaction {
+                                    changes: vec![history::FileChange {
+                                        path: edit.path.clone(),
+                                        old_content: edit.old_content,
+                                        new_content: Some(edit.new_content),
+                                    }],
+                                });
+                                app.messages.push(ChatMessage::new(
+                                    "system",
+                                    format!("已应用变更: {}", edit.path),
+                                ));
+                            }
+                            Err(e) => app.messages.push(ChatMessage::new(
+                                "system",
+                                format!("应用变更失败: {}", e),
+                            )),
+                        }
+                    } else if let Some(action) = app.pending_confirmations.pop() {
+                        let label = action.describe();
+                        let result = match action {
+                            diff::PendingConfirmation::Remove { path } => {
+                                tools::perform_remove(path).await
+                            }
+                            diff::PendingConfirmation::MoveContent {
+                                src,
+                                start_line,
+                                end_line,
+                                dst,
+                                dst_line,
+                            } => {
+                                tools::perform_move_content(src, start_line, end_line, dst, dst_line)
+                                    .await
+                            }
+                        };
+                        let msg = match result {
+                            Ok(t) => {
+                                app.history.record(t);
+                                format!("已应用变更: {}", label)
+                            }
+                            Err(e) => format!("应用变更失败: {}", e),
+                        };
+                        app.messages.push(ChatMessage::new("system", msg));
+                    }
+                    continue;
+                }
+                KeyCode::Char('n') => {
+                    if let Some(edit) = app.pending_edits.pop() {
+                        app.messages.push(ChatMessage::new(
+                            "system",
+                            format!("已丢弃变更: {}", edit.path),
+                        ));
+                    } else if let Some(action) = app.pending_confirmations.pop() {
+                        app.messages.push(ChatMessage::new(
+                            "system",
+                            format!("已丢弃变更: {}", action.describe()),
+                        ));
+                    }
+                    continue;
+                }
+                KeyCode::Up => {
+                    if !app.messages.is_empty() {
+                        let cur = app
+                            .selected_message
+                            .unwrap_or(app.messages.len().saturating_sub(1));
+                        app.selected_message = Some(cur.saturating_sub(1));
+                    }
+                    continue;
+                }
+                KeyCode::Down => {
+                    if !app.messages.is_empty() {
+                        let cur = app
+                            .selected_message
+                            .unwrap_or(app.messages.len().saturating_sub(1));
+                        app.selected_message = Some((cur + 1).min(app.messages.len() - 1));
+                    }
+                    continue;
+                }
+                KeyCode::Char('t') => {
+                    let idx = app
+                        .selected_message
+                        .unwrap_or(app.messages.len().saturating_sub(1));
+                    if let Some(msg) = app.messages.get_mut(idx) {
+                        if msg.summary.is_some() {
+                            msg.collapsed = !msg.collapsed;
+                        }
+                    }
+                    continue;
+                }
+                KeyCode::Char('c') => {
+                    if let Some(child) = app.running_child.take() {
+                        child.lock().await.kill().await.ok();
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+        }
+
         match key.code {
-            KeyCode::Esc => break,
+            KeyCode::Esc => {
+                if app.path_picker.take().is_some() {
+                    continue;
+                }
+                if let Some(child) = app.running_child.take() {
+                    child.lock().await.kill().await.ok();
+                    continue;
+                }
+                break;
+            }
             KeyCode::Enter => {
+                if let Some(picker) = app.path_picker.take() {
+                    if let Some(choice) = picker.candidates.get(picker.selected) {
+                        let mut parts: Vec<&str> = app.input.split_whitespace().collect();
+                        parts.pop();
+                        let mut new_input = parts.join(" ");
+                        if !new_input.is_empty() {
+                            new_input.push(' ');
+                        }
+                        new_input.push_str(choice);
+                        app.input = new_input;
+                    }
+                    continue;
+                }
                 if app.input.trim().is_empty() {
                     continue;
                 }
-                if let Some(cmd) = parse_command(&app.input) {
+                if let Some((name, cmd)) = parse_command(&app.input) {
                     let tx = app.events_tx.clone();
-                    tokio::spawn(async move { run_command(cmd, tx).await });
+                    tokio::spawn(async move { run_command(name, cmd, tx).await });
                     // 保持动画与重绘由事件驱动
                 } else {
-                    app.messages.push(("user".to_string(), app.input.clone()));
-                    app.status = Status::Requesting;
-                    terminal.draw(|f| ui(f, &app))?;
-                    if let Err(e) = stream_to_openai(&mut app, &mut terminal).await {
-                        app.messages
-                            .push(("system".to_string(), format!("请求失败: {}", e)));
-                        app.status = Status::Error(e.to_string());
+                    app.messages.push(ChatMessage::new("user", app.input.clone()));
+                    if app.provider.name() == "openai" {
+                        app.status = tui::Status::Requesting;
+                        terminal.draw(|f| ui(f, &app))?;
+                        if let Err(e) = stream_to_openai(&mut app, &mut terminal).await {
+                            app.messages.push(ChatMessage::new(
+                                "system",
+                                format!("请求失败: {}", e),
+                            ));
+                            app.status = tui::Status::Error(e.to_string());
+                        } else {
+                            app.status = tui::Status::Idle;
+                        }
                     } else {
-                        app.status = Status::Idle;
+                        app.messages.push(ChatMessage::new("assistant", String::new()));
+                        app.status = tui::Status::Streaming;
+                        let history: Vec<(String, String)> = app.messages
+                            [..app.messages.len() - 1]
+                            .iter()
+                            .map(|m| (m.role.clone(), m.content.clone()))
+                            .collect();
+                        let mut token_stream = app.provider.stream(&history);
+                        let tx = app.events_tx.clone();
+                        tokio::spawn(async move {
+                            use futures::StreamExt;
+                            while let Some(item) = token_stream.next().await {
+                                match item {
+                                    Ok(chunk) => {
+                                        tx.send(AppEvent::Token(chunk)).ok();
+                                    }
+                                    Err(e) => {
+                                        tx.send(AppEvent::System(format!("请求失败: {}", e)))
+                                            .ok();
+                                        tx.send(AppEvent::Status(tui::Status::Error(
+                                            e.to_string(),
+                                        )))
+                                        .ok();
+                                        return;
+                                    }
+                                }
+                            }
+                            tx.send(AppEvent::Status(tui::Status::Idle)).ok();
+                        });
                     }
                 }
                 app.input.clear();
@@ -590,7 +332,60 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 app.input.pop();
             }
             KeyCode::Tab => {
-                app.input.push('\t');
+                if let Some(picker) = &mut app.path_picker {
+                    let len = picker.candidates.len().max(1);
+                    picker.selected = (picker.selected + 1) % len;
+                } else {
+                    let token = app
+                        .input
+                        .split_whitespace()
+                        .last()
+                        .unwrap_or("")
+                        .to_string();
+                    if let Ok(paths) = complete::collect_paths().await {
+                        let candidates: Vec<String> = fuzzy::rank_candidates(&token, &paths)
+                            .into_iter()
+                            .take(8)
+                            .map(|(path, _, _)| path)
+                            .collect();
+                        if !candidates.is_empty() {
+                            app.path_picker = Some(PathPicker {
+                                candidates,
+                                selected: 0,
+                            });
+                        }
+                    }
+                }
+            }
+            KeyCode::BackTab => {
+                if let Some(picker) = &mut app.path_picker {
+                    let len = picker.candidates.len().max(1);
+                    picker.selected = (picker.selected + len - 1) % len;
+                }
+            }
+            KeyCode::Up => {
+                let cur = app.scroll_offset.unwrap_or(u16::MAX);
+                app.scroll_offset = Some(cur.saturating_sub(1));
+            }
+            KeyCode::Down => {
+                let cur = app.scroll_offset.unwrap_or(u16::MAX);
+                if cur != u16::MAX {
+                    app.scroll_offset = Some(cur.saturating_add(1));
+                }
+            }
+            KeyCode::PageUp => {
+                let cur = app.scroll_offset.unwrap_or(u16::MAX);
+                app.scroll_offset = Some(cur.saturating_sub(10));
+            }
+            KeyCode::PageDown => {
+                let cur = app.scroll_offset.unwrap_or(u16::MAX);
+                if cur != u16::MAX {
+                    app.scroll_offset = Some(cur.saturating_add(10));
+                }
+            }
+            KeyCode::End => {
+                // 回到底部，恢复自动跟随最新内容
+                app.scroll_offset = None;
             }
             _ => {}
         }
@@ -0,0 +1,320 @@
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use futures::{Stream, StreamExt};
+use openai::chat::{ChatCompletionDelta, ChatCompletionMessage, ChatCompletionMessageRole};
+use tokio::sync::mpsc::error::TryRecvError;
+
+/// 统一的模型补全后端接口：输入 (角色, 内容) 历史，产出增量文本块的流。
+/// 具体厂商只需把自己的流式协议适配成这一个 `Stream`，UI 与事件通道都不关心背后是哪家模型。
+pub trait CompletionProvider: Send + Sync {
+    /// 供主循环判断是否走原生工具调用路径（目前只有 `openai` 支持）。
+    fn name(&self) -> &'static str;
+
+    fn stream(
+        &self,
+        messages: &[(String, String)],
+    ) -> Pin<Box<dyn Stream<Item = Result<String, Box<dyn Error>>> + Send>>;
+
+    /// 为一段文本生成嵌入向量，供 `:search` 之类的语义检索功能使用。
+    /// 默认返回"不支持"错误；提供了嵌入接口的厂商（如 `openai`/`ollama`）需要覆盖此方法。
+    fn embed(&self, text: &str) -> Pin<Box<dyn Future<Output = Result<Vec<f32>, Box<dyn Error>>> + Send>> {
+        let name = self.name();
+        let _ = text;
+        Box::pin(async move { Err(format!("补全后端 {} 不支持生成嵌入向量", name).into()) })
+    }
+}
+
+fn to_chat_messages(messages: &[(String, String)]) -> Vec<ChatCompletionMessage> {
+    messages
+        .iter()
+        .map(|(role, content)| {
+            let role_enum = match role.as_str() {
+                "system" => ChatCompletionMessageRole::System,
+                "assistant" => ChatCompletionMessageRole::Assistant,
+                _ => ChatCompletionMessageRole::User,
+            };
+            ChatCompletionMessage {
+                role: role_enum,
+                content: Some(content.clone()),
+                name: None,
+                function_call: None,
+                tool_calls: None,
+                tool_call_id: None,
+            }
+        })
+        .collect()
+}
+
+/// OpenAI 嵌入端点使用的默认模型；`:search` 目前没有单独的配置项覆盖它。
+const OPENAI_EMBEDDING_MODEL: &str = "text-embedding-3-small";
+
+pub struct OpenAiProvider {
+    pub model: String,
+}
+
+impl OpenAiProvider {
+    pub fn from_env() -> Self {
+        Self {
+            model: std::env::var("OPENAI_MODEL").unwrap_or_else(|_| "gpt-4o-mini".to_string()),
+        }
+    }
+}
+
+impl CompletionProvider for OpenAiProvider {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    fn stream(
+        &self,
+        messages: &[(String, String)],
+    ) -> Pin<Box<dyn Stream<Item = Result<String, Box<dyn Error>>> + Send>> {
+        let model = self.model.clone();
+        let msgs = to_chat_messages(messages);
+        Box::pin(async_stream::stream! {
+            let mut chat_stream = match ChatCompletionDelta::builder(&model, msgs).create_stream().await {
+                Ok(s) => s,
+                Err(e) => {
+                    yield Err(Box::new(e) as Box<dyn Error>);
+                    return;
+                }
+            };
+            loop {
+                match chat_stream.try_recv() {
+                    Ok(delta) => {
+                        if let Some(content) = &delta.choices[0].delta.content {
+                            yield Ok(content.clone());
+                        }
+                    }
+                    Err(TryRecvError::Empty) => tokio::time::sleep(Duration::from_millis(50)).await,
+                    Err(TryRecvError::Disconnected) => break,
+                }
+            }
+        })
+    }
+
+    fn embed(&self, text: &str) -> Pin<Box<dyn Future<Output = Result<Vec<f32>, Box<dyn Error>>> + Send>> {
+        let text = text.to_string();
+        Box::pin(async move {
+            let response = openai::embeddings::Embeddings::create(
+                OPENAI_EMBEDDING_MODEL,
+                vec![text],
+                "meow-agent",
+            )
+            .await?;
+            Ok(response.data.into_iter().next().map(|e| e.vec).unwrap_or_default())
+        })
+    }
+}
+
+pub struct AnthropicProvider {
+    pub model: String,
+    pub api_key: String,
+}
+
+impl AnthropicProvider {
+    pub fn from_env() -> Self {
+        Self {
+            model: std::env::var("ANTHROPIC_MODEL")
+                .unwrap_or_else(|_| "claude-3-5-sonnet-latest".to_string()),
+            api_key: std::env::var("ANTHROPIC_API_KEY").unwrap_or_default(),
+        }
+    }
+}
+
+impl CompletionProvider for AnthropicProvider {
+    fn name(&self) -> &'static str {
+        "anthropic"
+    }
+
+    fn stream(
+        &self,
+        messages: &[(String, String)],
+    ) -> Pin<Box<dyn Stream<Item = Result<String, Box<dyn Error>>> + Send>> {
+        let model = self.model.clone();
+        let api_key = self.api_key.clone();
+        let system_prompt = messages
+            .iter()
+            .filter(|(role, _)| role == "system")
+            .map(|(_, content)| content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let turns: Vec<(String, String)> = messages
+            .iter()
+            .filter(|(role, _)| role != "system")
+            .cloned()
+            .collect();
+        let body = serde_json::json!({
+            "model": model,
+            "max_tokens": 4096,
+            "system": system_prompt,
+            "stream": true,
+            "messages": turns.iter().map(|(role, content)| serde_json::json!({
+                "role": if role == "assistant" { "assistant" } else { "user" },
+                "content": content,
+            })).collect::<Vec<_>>(),
+        });
+
+        Box::pin(async_stream::stream! {
+            let client = reqwest::Client::new();
+            let resp = match client
+                .post("https://api.anthropic.com/v1/messages")
+                .header("x-api-key", api_key)
+                .header("anthropic-version", "2023-06-01")
+                .json(&body)
+                .send()
+                .await
+            {
+                Ok(r) => r,
+                Err(e) => {
+                    yield Err(Box::new(e) as Box<dyn Error>);
+                    return;
+                }
+            };
+            let mut byte_stream = resp.bytes_stream();
+            let mut buf = String::new();
+            while let Some(chunk) = byte_stream.next().await {
+                let chunk = match chunk {
+                    Ok(c) => c,
+                    Err(e) => {
+                        yield Err(Box::new(e) as Box<dyn Error>);
+                        return;
+                    }
+                };
+                buf.push_str(&String::from_utf8_lossy(&chunk));
+                while let Some(pos) = buf.find('\n') {
+                    let line = buf[..pos].to_string();
+                    buf.drain(..=pos);
+                    let Some(data) = line.strip_prefix("data: ") else {
+                        continue;
+                    };
+                    let Ok(value) = serde_json::from_str::<serde_json::Value>(data) else {
+                        continue;
+                    };
+                    if value.get("type").and_then(|t| t.as_str()) == Some("content_block_delta")
+                        && let Some(text) = value
+                            .get("delta")
+                            .and_then(|d| d.get("text"))
+                            .and_then(|t| t.as_str())
+                    {
+                        yield Ok(text.to_string());
+                    }
+                }
+            }
+        })
+    }
+}
+
+pub struct OllamaProvider {
+    pub model: String,
+    pub api_base: String,
+    /// `:search` 用的嵌入模型；与对话用的 `model` 是两回事，需单独拉取/配置。
+    pub embedding_model: String,
+}
+
+impl OllamaProvider {
+    pub fn from_env() -> Self {
+        Self {
+            model: std::env::var("OLLAMA_MODEL").unwrap_or_else(|_| "llama3".to_string()),
+            api_base: std::env::var("OLLAMA_API_BASE")
+                .unwrap_or_else(|_| "http://localhost:11434".to_string()),
+            embedding_model: std::env::var("OLLAMA_EMBEDDING_MODEL")
+                .unwrap_or_else(|_| "nomic-embed-text".to_string()),
+        }
+    }
+}
+
+impl CompletionProvider for OllamaProvider {
+    fn name(&self) -> &'static str {
+        "ollama"
+    }
+
+    fn stream(
+        &self,
+        messages: &[(String, String)],
+    ) -> Pin<Box<dyn Stream<Item = Result<String, Box<dyn Error>>> + Send>> {
+        let model = self.model.clone();
+        let url = format!("{}/api/chat", self.api_base);
+        let body = serde_json::json!({
+            "model": model,
+            "stream": true,
+            "messages": messages.iter().map(|(role, content)| serde_json::json!({
+                "role": role,
+                "content": content,
+            })).collect::<Vec<_>>(),
+        });
+
+        Box::pin(async_stream::stream! {
+            let client = reqwest::Client::new();
+            let resp = match client.post(&url).json(&body).send().await {
+                Ok(r) => r,
+                Err(e) => {
+                    yield Err(Box::new(e) as Box<dyn Error>);
+                    return;
+                }
+            };
+            let mut byte_stream = resp.bytes_stream();
+            let mut buf = String::new();
+            while let Some(chunk) = byte_stream.next().await {
+                let chunk = match chunk {
+                    Ok(c) => c,
+                    Err(e) => {
+                        yield Err(Box::new(e) as Box<dyn Error>);
+                        return;
+                    }
+                };
+                buf.push_str(&String::from_utf8_lossy(&chunk));
+                while let Some(pos) = buf.find('\n') {
+                    let line = buf[..pos].to_string();
+                    buf.drain(..=pos);
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let Ok(value) = serde_json::from_str::<serde_json::Value>(&line) else {
+                        continue;
+                    };
+                    if let Some(text) = value
+                        .get("message")
+                        .and_then(|m| m.get("content"))
+                        .and_then(|c| c.as_str())
+                    {
+                        yield Ok(text.to_string());
+                    }
+                    if value.get("done").and_then(|d| d.as_bool()) == Some(true) {
+                        return;
+                    }
+                }
+            }
+        })
+    }
+
+    fn embed(&self, text: &str) -> Pin<Box<dyn Future<Output = Result<Vec<f32>, Box<dyn Error>>> + Send>> {
+        let text = text.to_string();
+        let model = self.embedding_model.clone();
+        let url = format!("{}/api/embeddings", self.api_base);
+        Box::pin(async move {
+            let client = reqwest::Client::new();
+            let body = serde_json::json!({ "model": model, "prompt": text });
+            let value: serde_json::Value =
+                client.post(&url).json(&body).send().await?.json().await?;
+            let vector = value
+                .get("embedding")
+                .and_then(|v| v.as_array())
+                .map(|arr| arr.iter().filter_map(|x| x.as_f64()).map(|x| x as f32).collect())
+                .unwrap_or_default();
+            Ok(vector)
+        })
+    }
+}
+
+/// 根据 `COMPLETION_PROVIDER` 环境变量选择后端（默认 `openai`），未识别的值也回退到 `openai`。
+pub fn select_provider() -> Box<dyn CompletionProvider> {
+    match std::env::var("COMPLETION_PROVIDER").as_deref() {
+        Ok("anthropic") => Box::new(AnthropicProvider::from_env()),
+        Ok("ollama") => Box::new(OllamaProvider::from_env()),
+        _ => Box::new(OpenAiProvider::from_env()),
+    }
+}
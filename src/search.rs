@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::providers::{self, CompletionProvider};
+use crate::tui::{AppEvent, Status};
+
+const CACHE_FILE: &str = ".meow_search_cache.json";
+const CHUNK_LINES: usize = 40;
+const CHUNK_STRIDE: usize = 10;
+const TOP_K: usize = 5;
+/// 超过这个字节数的文件视为过大，跳过索引（避免把整个 vendor/target 目录灌进嵌入请求）。
+const MAX_FILE_BYTES: usize = 200_000;
+
+/// 一段已编码的文本片段及其来源位置。
+#[derive(Serialize, Deserialize, Clone)]
+struct Chunk {
+    path: String,
+    start_line: usize,
+    end_line: usize,
+    mtime: u64,
+    content_hash: u64,
+    text: String,
+    vector: Vec<f32>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Cache {
+    chunks: Vec<Chunk>,
+}
+
+fn hash_text(text: &str) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+async fn file_mtime_secs(path: &Path) -> Option<u64> {
+    let meta = fs::metadata(path).await.ok()?;
+    let modified = meta.modified().ok()?;
+    modified.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+}
+
+async fn load_cache() -> Cache {
+    match fs::read_to_string(CACHE_FILE).await {
+        Ok(text) => serde_json::from_str(&text).unwrap_or_default(),
+        Err(_) => Cache::default(),
+    }
+}
+
+async fn save_cache(cache: &Cache) -> Result<(), Box<dyn Error>> {
+    let text = serde_json::to_string(cache)?;
+    fs::write(CACHE_FILE, text).await?;
+    Ok(())
+}
+
+/// 通过当前 `COMPLETION_PROVIDER` 配置的后端生成嵌入向量，而不是硬编码 OpenAI，
+/// 这样 `:search` 才会跟随用户选择的模型后端而不是默默忽略它。
+async fn embed(provider: &dyn CompletionProvider, text: &str) -> Result<Vec<f32>, Box<dyn Error>> {
+    provider.embed(text).await
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+/// 与 `FindCommand` 相同的栈式递归遍历，只保留可读的文本文件（按扩展名过滤）。
+async fn collect_text_files() -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let cwd = std::env::current_dir()?;
+    let mut found = Vec::new();
+    let mut stack = vec![cwd];
+    while let Some(dir) = stack.pop() {
+        let mut rd = fs::read_dir(&dir).await?;
+        while let Some(ent) = rd.next_entry().await? {
+            let p = ent.path();
+            if p.is_dir() {
+                stack.push(p);
+            } else if p
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|e| matches!(e, "rs" | "toml" | "md" | "txt" | "json"))
+                .unwrap_or(false)
+            {
+                found.push(p);
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// 读取文件文本，跳过过大或含 NUL 字节的二进制文件。
+async fn read_text_file(path: &Path) -> Option<String> {
+    let bytes = fs::read(path).await.ok()?;
+    if bytes.len() > MAX_FILE_BYTES || bytes.contains(&0) {
+        return None;
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// 增量重建工作目录的嵌入索引，并返回与 `query` 最相关的若干片段。
+/// `tx` 用于上报 `Indexing`/`Searching` 进度，供 TUI 展示状态。
+pub async fn reindex_and_search(
+    query: &str,
+    tx: &UnboundedSender<AppEvent>,
+) -> Result<String, Box<dyn Error>> {
+    tx.send(AppEvent::Status(Status::Indexing)).ok();
+
+    // 与 `App::new()` 一样读取 `COMPLETION_PROVIDER`，确保嵌入请求打到用户实际配置的后端。
+    let provider = providers::select_provider();
+
+    let old_cache = load_cache().await;
+    let mut by_path: HashMap<String, Vec<Chunk>> = HashMap::new();
+    for c in old_cache.chunks {
+        by_path.entry(c.path.clone()).or_default().push(c);
+    }
+
+    let files = collect_text_files().await?;
+    let mut fresh_chunks = Vec::new();
+    for path in files {
+        let path_str = path.display().to_string();
+        let Some(mtime) = file_mtime_secs(&path).await else {
+            continue;
+        };
+        let Some(text) = read_text_file(&path).await else {
+            continue;
+        };
+        let content_hash = hash_text(&text);
+
+        if let Some(existing) = by_path.get(&path_str) {
+            let unchanged = existing
+                .first()
+                .map(|c| c.mtime == mtime && c.content_hash == content_hash)
+                .unwrap_or(false);
+            if unchanged {
+                fresh_chunks.extend(existing.iter().cloned());
+                continue;
+            }
+        }
+
+        let lines: Vec<&str> = text.lines().collect();
+        let mut start = 0;
+        while start < lines.len() {
+            let end = (start + CHUNK_LINES).min(lines.len());
+            let chunk_text = lines[start..end].join("\n");
+            if !chunk_text.trim().is_empty() {
+                let vector = embed(provider.as_ref(), &chunk_text).await?;
+                fresh_chunks.push(Chunk {
+                    path: path_str.clone(),
+                    start_line: start + 1,
+                    end_line: end,
+                    mtime,
+                    content_hash,
+                    text: chunk_text,
+                    vector,
+                });
+            }
+            if end == lines.len() {
+                break;
+            }
+            start += CHUNK_STRIDE;
+        }
+    }
+
+    let new_cache = Cache {
+        chunks: fresh_chunks,
+    };
+    save_cache(&new_cache).await?;
+
+    tx.send(AppEvent::Status(Status::Searching)).ok();
+
+    let query_vec = embed(provider.as_ref(), query).await?;
+    let mut scored: Vec<(f32, &Chunk)> = new_cache
+        .chunks
+        .iter()
+        .map(|c| (cosine(&query_vec, &c.vector), c))
+        .collect();
+    scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let top: Vec<String> = scored
+        .into_iter()
+        .take(TOP_K)
+        .map(|(score, c)| {
+            let snippet: String = c.text.lines().take(3).collect::<Vec<_>>().join("\n  ");
+            format!(
+                "{}:{}-{} (相似度 {:.3})\n  {}",
+                c.path, c.start_line, c.end_line, score, snippet
+            )
+        })
+        .collect();
+
+    Ok(format!(
+        "检索到 {} 个相关片段:\n{}",
+        top.len(),
+        top.join("\n")
+    ))
+}
@@ -0,0 +1,76 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::tui::ChatMessage;
+
+const SESSION_DIR: &str = ".meow-agent/sessions";
+
+/// 一次对话的可序列化快照：模型选择与完整消息历史。
+#[derive(Serialize, Deserialize)]
+pub struct SessionData {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+}
+
+/// `name` 来自 `:save`/`:load <name>`，也可能来自模型的工具调用参数；只允许字母、数字、
+/// 下划线与短横线，拒绝 `/`、`\`、`..` 等，防止拼出 `SESSION_DIR` 之外的任意路径。
+fn validate_session_name(name: &str) -> Result<(), Box<dyn Error>> {
+    if !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        Ok(())
+    } else {
+        Err(format!("非法的会话名 '{}'：仅允许字母、数字、下划线与短横线", name).into())
+    }
+}
+
+fn session_path(name: &str) -> Result<PathBuf, Box<dyn Error>> {
+    validate_session_name(name)?;
+    Ok(Path::new(SESSION_DIR).join(format!("{}.json", name)))
+}
+
+pub async fn save(name: &str, model: &str, messages: &[ChatMessage]) -> Result<(), Box<dyn Error>> {
+    let path = session_path(name)?;
+    fs::create_dir_all(SESSION_DIR).await?;
+    let data = SessionData {
+        model: model.to_string(),
+        messages: messages.to_vec(),
+    };
+    let text = serde_json::to_string_pretty(&data)?;
+    fs::write(path, text).await?;
+    Ok(())
+}
+
+pub async fn load(name: &str) -> Result<SessionData, Box<dyn Error>> {
+    let path = session_path(name)?;
+    let text = fs::read_to_string(path).await?;
+    Ok(serde_json::from_str(&text)?)
+}
+
+/// 返回修改时间最新的会话名（不含扩展名），用于启动时续接上次对话。
+pub async fn most_recent() -> Option<String> {
+    let mut rd = fs::read_dir(SESSION_DIR).await.ok()?;
+    let mut best: Option<(std::time::SystemTime, String)> = None;
+    while let Ok(Some(ent)) = rd.next_entry().await {
+        let path = ent.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let (Ok(meta), Some(stem)) = (ent.metadata().await, path.file_stem().and_then(|s| s.to_str()))
+        else {
+            continue;
+        };
+        let Ok(modified) = meta.modified() else {
+            continue;
+        };
+        if best.as_ref().map(|(t, _)| modified > *t).unwrap_or(true) {
+            best = Some((modified, stem.to_string()));
+        }
+    }
+    best.map(|(_, name)| name)
+}
@@ -1,15 +1,23 @@
 use std::error::Error;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::Arc;
+
+use serde_json::{Value, json};
+use tokio::sync::Mutex;
 use tokio::sync::mpsc::UnboundedSender;
 
+use crate::diff;
+use crate::fuzzy;
+use crate::search;
 use crate::tui::{AppEvent, Status};
 
 pub type CmdResult = Result<String, Box<dyn Error>>;
 pub type CmdFuture = Pin<Box<dyn Future<Output = CmdResult> + Send>>;
 
 pub trait ToolCommand: Send {
-    fn execute(&self) -> CmdFuture;
+    /// `tx` 用于提交不适合直接作为返回值的副作用事件（例如待确认的编辑）。
+    fn execute(&self, tx: UnboundedSender<AppEvent>) -> CmdFuture;
 }
 
 struct TouchCommand {
@@ -17,7 +25,7 @@ struct TouchCommand {
 }
 
 impl ToolCommand for TouchCommand {
-    fn execute(&self) -> CmdFuture {
+    fn execute(&self, _tx: UnboundedSender<AppEvent>) -> CmdFuture {
         let path = self.path.clone();
         Box::pin(async move {
             use tokio::fs;
@@ -32,29 +40,54 @@ struct RmCommand {
 }
 
 impl ToolCommand for RmCommand {
-    fn execute(&self) -> CmdFuture {
+    fn execute(&self, tx: UnboundedSender<AppEvent>) -> CmdFuture {
         let path = self.path.clone();
         Box::pin(async move {
-            use tokio::fs;
-            fs::remove_file(path).await?;
-            Ok("文件已删除".to_string())
+            tx.send(AppEvent::ConfirmRequested(diff::PendingConfirmation::Remove {
+                path: path.clone(),
+            }))
+            .ok();
+            Ok(format!(
+                "待确认删除已生成（Ctrl+Y 应用 / Ctrl+N 丢弃）：rm {}",
+                path
+            ))
         })
     }
 }
 
+/// 实际执行 `rm`：在 Ctrl+Y 确认后由主循环调用，返回供历史记录的修订。
+pub(crate) async fn perform_remove(path: String) -> Result<crate::history::Transaction, Box<dyn Error>> {
+    use tokio::fs;
+    let old_content = fs::read_to_string(&path).await.ok();
+    fs::remove_file(&path).await?;
+    Ok(crate::history::Transaction {
+        changes: vec![crate::history::FileChange {
+            path,
+            old_content,
+            new_content: None,
+        }],
+    })
+}
+
 struct WriteCommand {
     path: String,
     content: String,
 }
 
 impl ToolCommand for WriteCommand {
-    fn execute(&self) -> CmdFuture {
+    fn execute(&self, tx: UnboundedSender<AppEvent>) -> CmdFuture {
         let path = self.path.clone();
         let content = self.content.clone();
         Box::pin(async move {
             use tokio::fs;
-            fs::write(path, content).await?;
-            Ok("文件已写入".to_string())
+            let old_content = fs::read_to_string(&path).await.ok();
+            let pending = diff::build_pending_edit(path, old_content.as_deref(), content);
+            let preview = format!("已生成待确认的 diff（共 {} 行）", pending.diff.len());
+            tx.send(AppEvent::Edit(pending)).ok();
+            Ok(format!(
+                "待确认写入已生成（Ctrl+Y 应用 / Ctrl+N 丢弃）：{}",
+                preview
+            ))
         })
     }
 }
@@ -64,12 +97,12 @@ struct FindCommand {
 }
 
 impl ToolCommand for FindCommand {
-    fn execute(&self) -> CmdFuture {
+    fn execute(&self, _tx: UnboundedSender<AppEvent>) -> CmdFuture {
         let pattern = self.pattern.clone();
         Box::pin(async move {
             use tokio::fs;
             let cwd = std::env::current_dir()?;
-            let mut found: Vec<String> = Vec::new();
+            let mut candidates: Vec<String> = Vec::new();
             let mut stack = vec![cwd];
             while let Some(dir) = stack.pop() {
                 let mut rd = fs::read_dir(&dir).await?;
@@ -77,13 +110,13 @@ impl ToolCommand for FindCommand {
                     let p = ent.path();
                     if p.is_dir() {
                         stack.push(p);
-                    } else if let Some(name) = p.file_name().and_then(|s| s.to_str())
-                        && name.contains(&pattern)
-                    {
-                        found.push(p.display().to_string());
+                    } else {
+                        candidates.push(p.display().to_string());
                     }
                 }
             }
+            let ranked = fuzzy::rank_candidates(&pattern, &candidates);
+            let found: Vec<String> = ranked.into_iter().map(|(path, _, _)| path).collect();
             Ok(format!(
                 "匹配到 {} 个文件:\n{}",
                 found.len(),
@@ -93,33 +126,103 @@ impl ToolCommand for FindCommand {
     }
 }
 
+/// 检测文本的主要换行风格：`\r\n` 出现次数达到换行总数的一半以上时判定为 CRLF。
+/// `line`/`col` 坐标与偏移换算都依赖这个判断，以免把 CRLF 文件悄悄改写成 LF。
+fn detect_line_ending(text: &str) -> &'static str {
+    let crlf = text.matches("\r\n").count();
+    let total_newlines = text.matches('\n').count();
+    if total_newlines > 0 && crlf * 2 >= total_newlines {
+        "\r\n"
+    } else {
+        "\n"
+    }
+}
+
+/// 按 `line`（从 1 开始）定位该行在 `text` 中的起始字节偏移及行内容（已去除换行符）。
+fn line_start_offset<'a>(text: &'a str, line: usize, eol: &str) -> Option<(usize, &'a str)> {
+    let mut offset = 0usize;
+    for (i, l) in text.lines().enumerate() {
+        if i + 1 == line {
+            return Some((offset, l));
+        }
+        offset += l.len() + eol.len();
+    }
+    None
+}
+
+/// 把以字符（Unicode scalar）计的列号换算成 `line_text` 内的字节偏移，超出行尾则截断到行末。
+///
+/// 已知局限：这里按 `char_indices()`（Unicode 标量值）计数，不是真正的字形簇
+/// （grapheme cluster）。像带组合记号的字符或多标量值的 ZWJ emoji 序列会被
+/// 算作多个 `col`，调用方传入"第 N 个字符"时可能把这类簇从中间切开。若要与
+/// "字符坐标＝字形簇"的语义精确对齐，需要引入 grapheme-aware 的实现。
+fn char_col_to_byte(line_text: &str, col: usize) -> usize {
+    line_text
+        .char_indices()
+        .nth(col)
+        .map(|(b, _)| b)
+        .unwrap_or(line_text.len())
+}
+
+/// 把 (line, col) 字符坐标换算成 `text` 中的字节偏移；行号越界时钳制到文本末尾。
+fn char_pos_to_byte_offset(text: &str, line: usize, col: usize, eol: &str) -> usize {
+    match line_start_offset(text, line, eol) {
+        Some((base, line_text)) => base + char_col_to_byte(line_text, col),
+        None => text.len(),
+    }
+}
+
+/// 跳过前导空白后取出下一个以空白分隔的 token，返回 `(token, token 之后剩余的原文)`。
+/// 直接在原始字符串上按字节位置切片，不依赖重建字符串再匹配，token 间的多余空白不会
+/// 导致解析失败。
+fn next_token(s: &str) -> Option<(&str, &str)> {
+    let trimmed = s.trim_start();
+    let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+    if end == 0 {
+        return None;
+    }
+    Some((&trimmed[..end], &trimmed[end..]))
+}
+
 struct EditAtCommand {
     path: String,
     line: usize,
     col: usize,
+    /// 结束位置（字符坐标）；`Some` 时替换 [start, end) 范围而不是单纯插入。
+    end: Option<(usize, usize)>,
     content: String,
 }
 
 impl ToolCommand for EditAtCommand {
-    fn execute(&self) -> CmdFuture {
+    fn execute(&self, tx: UnboundedSender<AppEvent>) -> CmdFuture {
         let path = self.path.clone();
         let line = self.line;
         let col = self.col;
+        let end = self.end;
         let content = self.content.clone();
         Box::pin(async move {
             use tokio::fs;
-            let mut text = fs::read_to_string(&path).await?;
-            let mut offset = 0usize;
-            for (i, l) in text.lines().enumerate() {
-                if i + 1 == line {
-                    offset += col.min(l.len());
-                    break;
+            let old_text = fs::read_to_string(&path).await?;
+            let eol = detect_line_ending(&old_text);
+            let start_offset = char_pos_to_byte_offset(&old_text, line, col, eol);
+            let mut new_text = old_text.clone();
+            match end {
+                Some((end_line, end_col)) => {
+                    let end_offset =
+                        char_pos_to_byte_offset(&old_text, end_line, end_col, eol).max(start_offset);
+                    new_text.replace_range(start_offset..end_offset, &content);
+                }
+                None => {
+                    new_text.insert_str(start_offset, &content);
                 }
-                offset += l.len() + 1; // +\n
             }
-            text.insert_str(offset, &content);
-            fs::write(path, text).await?;
-            Ok("已定点插入内容".to_string())
+            let pending = diff::build_pending_edit(path, Some(&old_text), new_text);
+            let preview = format!("已生成待确认的 diff（共 {} 行）", pending.diff.len());
+            tx.send(AppEvent::Edit(pending)).ok();
+            Ok(format!(
+                "待确认编辑已生成（Ctrl+Y 应用 / Ctrl+N 丢弃）：{}",
+                preview
+            ))
         })
     }
 }
@@ -133,46 +236,236 @@ struct MoveContentCommand {
 }
 
 impl ToolCommand for MoveContentCommand {
-    fn execute(&self) -> CmdFuture {
+    fn execute(&self, tx: UnboundedSender<AppEvent>) -> CmdFuture {
         let src = self.src.clone();
         let start_line = self.start_line;
         let end_line = self.end_line;
         let dst = self.dst.clone();
         let dst_line = self.dst_line;
         Box::pin(async move {
-            use tokio::fs;
-            let mut src_text = fs::read_to_string(&src).await?;
-            let dst_text = fs::read_to_string(&dst).await?;
-
-            let lines: Vec<&str> = src_text.lines().collect();
-            let start = start_line.saturating_sub(1);
-            let end = end_line.min(lines.len());
-            let moving = lines[start..end].join("\n");
-
-            // remove from src
-            let mut new_src = String::new();
-            for (i, l) in lines.iter().enumerate() {
-                if i < start || i >= end {
-                    new_src.push_str(l);
-                    new_src.push('\n');
-                }
-            }
-            src_text = new_src;
+            tx.send(AppEvent::ConfirmRequested(
+                diff::PendingConfirmation::MoveContent {
+                    src: src.clone(),
+                    start_line,
+                    end_line,
+                    dst: dst.clone(),
+                    dst_line,
+                },
+            ))
+            .ok();
+            Ok(format!(
+                "待确认移动已生成（Ctrl+Y 应用 / Ctrl+N 丢弃）：move-content {}:{}-{} -> {}:{}",
+                src, start_line, end_line, dst, dst_line
+            ))
+        })
+    }
+}
+
+/// 实际执行 `move-content`：在 Ctrl+Y 确认后由主循环调用，返回供历史记录的修订。
+pub(crate) async fn perform_move_content(
+    src: String,
+    start_line: usize,
+    end_line: usize,
+    dst: String,
+    dst_line: usize,
+) -> Result<crate::history::Transaction, Box<dyn Error>> {
+    use tokio::fs;
+    let mut src_text = fs::read_to_string(&src).await?;
+    let old_src_text = src_text.clone();
+    let dst_text = fs::read_to_string(&dst).await?;
 
-            // insert into dst
-            let mut offset = 0usize;
-            for (i, l) in dst_text.lines().enumerate() {
-                if i + 1 == dst_line {
-                    break;
+    // 分别检测 src/dst 的换行风格，搬运内容时各自保留，不强制改写成 LF。
+    let src_eol = detect_line_ending(&src_text);
+    let dst_eol = detect_line_ending(&dst_text);
+
+    let lines: Vec<&str> = src_text.lines().collect();
+    let start = start_line.saturating_sub(1);
+    let end = end_line.min(lines.len());
+    let moving = lines[start..end].join(dst_eol);
+
+    // remove from src
+    let mut new_src = String::new();
+    for (i, l) in lines.iter().enumerate() {
+        if i < start || i >= end {
+            new_src.push_str(l);
+            new_src.push_str(src_eol);
+        }
+    }
+    src_text = new_src;
+
+    // insert into dst
+    let mut offset = 0usize;
+    for (i, l) in dst_text.lines().enumerate() {
+        if i + 1 == dst_line {
+            break;
+        }
+        offset += l.len() + dst_eol.len();
+    }
+    // `dst_line` 越过文件末尾时（尤其是文件没有结尾换行符），上面按行累加出的
+    // offset 可能比 dst_text 实际字节数还大；钳制到文本末尾，否则 insert_str
+    // 会在字符边界之外插入而 panic。
+    let offset = offset.min(dst_text.len());
+    let mut dst_new = dst_text.clone();
+    dst_new.insert_str(offset, &format!("{}{}", moving, dst_eol));
+
+    fs::write(&src, &src_text).await?;
+    fs::write(&dst, &dst_new).await?;
+    Ok(crate::history::Transaction {
+        changes: vec![
+            crate::history::FileChange {
+                path: src,
+                old_content: Some(old_src_text),
+                new_content: Some(src_text),
+            },
+            crate::history::FileChange {
+                path: dst,
+                old_content: Some(dst_text),
+                new_content: Some(dst_new),
+            },
+        ],
+    })
+}
+
+struct SearchCommand {
+    query: String,
+}
+
+impl ToolCommand for SearchCommand {
+    fn execute(&self, tx: UnboundedSender<AppEvent>) -> CmdFuture {
+        let query = self.query.clone();
+        Box::pin(async move { search::reindex_and_search(&query, &tx).await })
+    }
+}
+
+struct SaveCommand {
+    name: String,
+}
+
+impl ToolCommand for SaveCommand {
+    fn execute(&self, tx: UnboundedSender<AppEvent>) -> CmdFuture {
+        let name = self.name.clone();
+        Box::pin(async move {
+            tx.send(AppEvent::SaveRequested(name.clone())).ok();
+            Ok(format!("正在保存会话 '{}'…", name))
+        })
+    }
+}
+
+struct LoadCommand {
+    name: String,
+}
+
+impl ToolCommand for LoadCommand {
+    fn execute(&self, tx: UnboundedSender<AppEvent>) -> CmdFuture {
+        let name = self.name.clone();
+        Box::pin(async move {
+            tx.send(AppEvent::LoadRequested(name.clone())).ok();
+            Ok(format!("正在加载会话 '{}'…", name))
+        })
+    }
+}
+
+struct RunCommand {
+    cmd: String,
+}
+
+impl ToolCommand for RunCommand {
+    fn execute(&self, tx: UnboundedSender<AppEvent>) -> CmdFuture {
+        let cmd_line = self.cmd.clone();
+        Box::pin(async move {
+            use std::process::Stdio;
+            use tokio::io::{AsyncBufReadExt, BufReader};
+            use tokio::process::Command;
+
+            let mut child = Command::new("sh")
+                .arg("-c")
+                .arg(&cmd_line)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()?;
+
+            let stdout = child.stdout.take().ok_or("无法捕获子进程 stdout")?;
+            let stderr = child.stderr.take().ok_or("无法捕获子进程 stderr")?;
+            let child = Arc::new(Mutex::new(child));
+            tx.send(AppEvent::ProcessStarted(child.clone())).ok();
+
+            let tx_out = tx.clone();
+            let out_task = tokio::spawn(async move {
+                let mut lines = BufReader::new(stdout).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    tx_out.send(AppEvent::System(line)).ok();
                 }
-                offset += l.len() + 1;
-            }
-            let mut dst_new = dst_text.clone();
-            dst_new.insert_str(offset, &format!("{}\n", moving));
+            });
+            let tx_err = tx.clone();
+            let err_task = tokio::spawn(async move {
+                let mut lines = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    tx_err
+                        .send(AppEvent::System(format!("[stderr] {}", line)))
+                        .ok();
+                }
+            });
+            out_task.await.ok();
+            err_task.await.ok();
 
-            fs::write(&src, src_text).await?;
-            fs::write(&dst, dst_new).await?;
-            Ok("已移动内容".to_string())
+            let status = child.lock().await.wait().await?;
+            let code = status.code();
+            tx.send(AppEvent::ProcessFinished(code)).ok();
+            Ok(format!(
+                "命令执行完毕，退出码: {}",
+                code.map(|c| c.to_string())
+                    .unwrap_or_else(|| "被终止".to_string())
+            ))
+        })
+    }
+}
+
+struct UndoCommand;
+
+impl ToolCommand for UndoCommand {
+    fn execute(&self, tx: UnboundedSender<AppEvent>) -> CmdFuture {
+        Box::pin(async move {
+            tx.send(AppEvent::UndoRequested).ok();
+            Ok("正在撤销最近一次变更…".to_string())
+        })
+    }
+}
+
+struct RedoCommand;
+
+impl ToolCommand for RedoCommand {
+    fn execute(&self, tx: UnboundedSender<AppEvent>) -> CmdFuture {
+        Box::pin(async move {
+            tx.send(AppEvent::RedoRequested).ok();
+            Ok("正在重做最近一次撤销…".to_string())
+        })
+    }
+}
+
+struct EarlierCommand {
+    kind: crate::history::UndoKind,
+}
+
+impl ToolCommand for EarlierCommand {
+    fn execute(&self, tx: UnboundedSender<AppEvent>) -> CmdFuture {
+        let kind = self.kind;
+        Box::pin(async move {
+            tx.send(AppEvent::EarlierRequested(kind)).ok();
+            Ok("正在回溯历史…".to_string())
+        })
+    }
+}
+
+struct LaterCommand {
+    kind: crate::history::UndoKind,
+}
+
+impl ToolCommand for LaterCommand {
+    fn execute(&self, tx: UnboundedSender<AppEvent>) -> CmdFuture {
+        let kind = self.kind;
+        Box::pin(async move {
+            tx.send(AppEvent::LaterRequested(kind)).ok();
+            Ok("正在前进历史…".to_string())
         })
     }
 }
@@ -180,6 +473,19 @@ impl ToolCommand for MoveContentCommand {
 pub trait CommandSpec {
     fn name(&self) -> &'static str;
     fn parse(&self, args: &str) -> Option<Box<dyn ToolCommand + Send>>;
+
+    /// OpenAI 函数调用使用的工具描述（`{"type": "function", "function": {...}}`）。
+    fn tool_schema(&self) -> Value;
+
+    /// 根据模型返回的工具调用参数（JSON 对象）构造命令。
+    fn from_tool_args(&self, args: &Value) -> Option<Box<dyn ToolCommand + Send>>;
+
+    /// 是否允许模型在自动工具调用循环中直接调用（见 `tool_schemas`）。
+    /// 默认 `true`；`run` 这类不受确认闸门约束的任意 shell 执行需要覆盖为 `false`，
+    /// 只保留给人类通过 `:run` 显式调用。
+    fn model_callable(&self) -> bool {
+        true
+    }
 }
 
 struct TouchSpec;
@@ -194,6 +500,26 @@ impl CommandSpec for TouchSpec {
             path: path.to_string(),
         }))
     }
+    fn tool_schema(&self) -> Value {
+        json!({
+            "type": "function",
+            "function": {
+                "name": "touch",
+                "description": "创建一个空文件",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string", "description": "目标文件路径" }
+                    },
+                    "required": ["path"]
+                }
+            }
+        })
+    }
+    fn from_tool_args(&self, args: &Value) -> Option<Box<dyn ToolCommand + Send>> {
+        let path = args.get("path")?.as_str()?.to_string();
+        Some(Box::new(TouchCommand { path }))
+    }
 }
 
 struct RmSpec;
@@ -208,6 +534,26 @@ impl CommandSpec for RmSpec {
             path: path.to_string(),
         }))
     }
+    fn tool_schema(&self) -> Value {
+        json!({
+            "type": "function",
+            "function": {
+                "name": "rm",
+                "description": "删除一个文件",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string", "description": "要删除的文件路径" }
+                    },
+                    "required": ["path"]
+                }
+            }
+        })
+    }
+    fn from_tool_args(&self, args: &Value) -> Option<Box<dyn ToolCommand + Send>> {
+        let path = args.get("path")?.as_str()?.to_string();
+        Some(Box::new(RmCommand { path }))
+    }
 }
 
 struct WriteSpec;
@@ -224,6 +570,31 @@ impl CommandSpec for WriteSpec {
             content: content.to_string(),
         }))
     }
+    fn tool_schema(&self) -> Value {
+        json!({
+            "type": "function",
+            "function": {
+                "name": "write",
+                "description": "将给定内容整体写入文件（覆盖原有内容）",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string", "description": "目标文件路径" },
+                        "content": { "type": "string", "description": "要写入的完整文件内容" }
+                    },
+                    "required": ["path", "content"]
+                }
+            }
+        })
+    }
+    fn from_tool_args(&self, args: &Value) -> Option<Box<dyn ToolCommand + Send>> {
+        let path = args.get("path")?.as_str()?.to_string();
+        let content = args.get("content").and_then(|v| v.as_str()).unwrap_or("");
+        Some(Box::new(WriteCommand {
+            path,
+            content: content.to_string(),
+        }))
+    }
 }
 
 struct FindSpec;
@@ -237,6 +608,26 @@ impl CommandSpec for FindSpec {
             pattern: pattern.to_string(),
         }))
     }
+    fn tool_schema(&self) -> Value {
+        json!({
+            "type": "function",
+            "function": {
+                "name": "find",
+                "description": "在当前工作目录下递归查找路径，按子序列模糊匹配打分并按分数降序返回",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "pattern": { "type": "string", "description": "模糊匹配的查询字符串（按子序列匹配路径，如 \"srvmn\" 可匹配 \"server/main.rs\"）" }
+                    },
+                    "required": ["pattern"]
+                }
+            }
+        })
+    }
+    fn from_tool_args(&self, args: &Value) -> Option<Box<dyn ToolCommand + Send>> {
+        let pattern = args.get("pattern")?.as_str()?.to_string();
+        Some(Box::new(FindCommand { pattern }))
+    }
 }
 
 struct EditAtSpec;
@@ -245,22 +636,91 @@ impl CommandSpec for EditAtSpec {
         "edit-at"
     }
     fn parse(&self, args: &str) -> Option<Box<dyn ToolCommand + Send>> {
-        let mut parts = args.split_whitespace();
-        let path = parts.next()?.to_string();
-        let line: usize = parts.next()?.parse().ok()?;
-        let col: usize = parts.next()?.parse().ok()?;
-        // 剩余内容作为待插入文本
-        let consumed = format!("{} {} {}", path, line, col);
-        let content = args
-            .strip_prefix(&consumed)
-            .and_then(|s| s.strip_prefix(' '))
-            .unwrap_or("")
-            .to_string();
+        // 按字节位置在原始字符串上切片取 token，而不是把已解析的 token 重新拼接成
+        // `consumed` 字符串再用 `strip_prefix` 去原文里匹配——token 之间出现多余/不规则
+        // 空白（比如缩进代码前的双空格）会让重建出的字符串对不上原文，导致 `rest` 悄悄
+        // 变成空串、content 被整段丢弃。
+        let rest = args;
+        let (path, rest) = next_token(rest)?;
+        let path = path.to_string();
+        let (line_tok, rest) = next_token(rest)?;
+        let line: usize = line_tok.parse().ok()?;
+        let (col_tok, rest) = next_token(rest)?;
+        let col: usize = col_tok.parse().ok()?;
+        // 只跳过紧跟 col 的那一个分隔空白，多出来的前导空白原样保留进 content（缩进）。
+        let rest = match rest.strip_prefix(' ').or_else(|| rest.strip_prefix('\t')) {
+            Some(r) => r,
+            None => rest,
+        };
+
+        // range 替换模式需要显式的 `to` 分隔符；没有它就整段 rest 都是待插入文本，
+        // 否则 `:edit-at f.rs 3 0 42 100 hello` 这种以数字开头的插入内容会被
+        // 误判成结束行列 (42, 100)。
+        if let Some(after_to) = rest.strip_prefix("to ") {
+            let mut range_parts = after_to.splitn(3, ' ');
+            let maybe_end_line = range_parts.next();
+            let maybe_end_col = range_parts.next();
+            if let (Some(el), Some(ec)) = (maybe_end_line, maybe_end_col)
+                && let (Ok(end_line), Ok(end_col)) = (el.parse::<usize>(), ec.parse::<usize>())
+            {
+                let content = range_parts.next().unwrap_or("").to_string();
+                return Some(Box::new(EditAtCommand {
+                    path,
+                    line,
+                    col,
+                    end: Some((end_line, end_col)),
+                    content,
+                }));
+            }
+        }
+
+        Some(Box::new(EditAtCommand {
+            path,
+            line,
+            col,
+            end: None,
+            content: rest.to_string(),
+        }))
+    }
+    fn tool_schema(&self) -> Value {
+        json!({
+            "type": "function",
+            "function": {
+                "name": "edit-at",
+                "description": "在文件的指定字符坐标插入文本，或在给定 end_line/end_col 时替换该范围",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string", "description": "目标文件路径" },
+                        "line": { "type": "integer", "description": "起始行号（从 1 开始）" },
+                        "col": { "type": "integer", "description": "起始列号（从 0 开始，按 Unicode 标量值计，非字形簇，组合字符/emoji 序列可能被拆分）" },
+                        "end_line": { "type": "integer", "description": "可选：结束行号，提供时替换 [start, end) 范围而非插入" },
+                        "end_col": { "type": "integer", "description": "可选：结束列号（按 Unicode 标量值计，同 col），需与 end_line 同时提供" },
+                        "content": { "type": "string", "description": "要插入/替换成的文本" }
+                    },
+                    "required": ["path", "line", "col", "content"]
+                }
+            }
+        })
+    }
+    fn from_tool_args(&self, args: &Value) -> Option<Box<dyn ToolCommand + Send>> {
+        let path = args.get("path")?.as_str()?.to_string();
+        let line = args.get("line")?.as_u64()? as usize;
+        let col = args.get("col")?.as_u64()? as usize;
+        let content = args.get("content").and_then(|v| v.as_str()).unwrap_or("");
+        let end = match (
+            args.get("end_line").and_then(|v| v.as_u64()),
+            args.get("end_col").and_then(|v| v.as_u64()),
+        ) {
+            (Some(el), Some(ec)) => Some((el as usize, ec as usize)),
+            _ => None,
+        };
         Some(Box::new(EditAtCommand {
             path,
             line,
             col,
-            content,
+            end,
+            content: content.to_string(),
         }))
     }
 }
@@ -285,9 +745,294 @@ impl CommandSpec for MoveContentSpec {
             dst_line,
         }))
     }
+    fn tool_schema(&self) -> Value {
+        json!({
+            "type": "function",
+            "function": {
+                "name": "move-content",
+                "description": "将源文件中的一段行范围移动到目标文件的指定行",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "src": { "type": "string", "description": "源文件路径" },
+                        "start_line": { "type": "integer", "description": "起始行号（含，从 1 开始）" },
+                        "end_line": { "type": "integer", "description": "结束行号（含，从 1 开始）" },
+                        "dst": { "type": "string", "description": "目标文件路径" },
+                        "dst_line": { "type": "integer", "description": "插入到目标文件的行号（从 1 开始）" }
+                    },
+                    "required": ["src", "start_line", "end_line", "dst", "dst_line"]
+                }
+            }
+        })
+    }
+    fn from_tool_args(&self, args: &Value) -> Option<Box<dyn ToolCommand + Send>> {
+        let src = args.get("src")?.as_str()?.to_string();
+        let start_line = args.get("start_line")?.as_u64()? as usize;
+        let end_line = args.get("end_line")?.as_u64()? as usize;
+        let dst = args.get("dst")?.as_str()?.to_string();
+        let dst_line = args.get("dst_line")?.as_u64()? as usize;
+        Some(Box::new(MoveContentCommand {
+            src,
+            start_line,
+            end_line,
+            dst,
+            dst_line,
+        }))
+    }
+}
+
+struct SearchSpec;
+impl CommandSpec for SearchSpec {
+    fn name(&self) -> &'static str {
+        "search"
+    }
+    fn parse(&self, args: &str) -> Option<Box<dyn ToolCommand + Send>> {
+        if args.trim().is_empty() {
+            return None;
+        }
+        Some(Box::new(SearchCommand {
+            query: args.trim().to_string(),
+        }))
+    }
+    fn tool_schema(&self) -> Value {
+        json!({
+            "type": "function",
+            "function": {
+                "name": "search",
+                "description": "在工作目录中基于语义（嵌入向量）检索与查询最相关的文件片段",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "query": { "type": "string", "description": "自然语言查询" }
+                    },
+                    "required": ["query"]
+                }
+            }
+        })
+    }
+    fn from_tool_args(&self, args: &Value) -> Option<Box<dyn ToolCommand + Send>> {
+        let query = args.get("query")?.as_str()?.to_string();
+        Some(Box::new(SearchCommand { query }))
+    }
+}
+
+struct SaveSpec;
+impl CommandSpec for SaveSpec {
+    fn name(&self) -> &'static str {
+        "save"
+    }
+    fn parse(&self, args: &str) -> Option<Box<dyn ToolCommand + Send>> {
+        let name = args.split_whitespace().next()?;
+        Some(Box::new(SaveCommand {
+            name: name.to_string(),
+        }))
+    }
+    fn tool_schema(&self) -> Value {
+        json!({
+            "type": "function",
+            "function": {
+                "name": "save",
+                "description": "将当前对话保存为命名会话",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string", "description": "会话名称" }
+                    },
+                    "required": ["name"]
+                }
+            }
+        })
+    }
+    fn from_tool_args(&self, args: &Value) -> Option<Box<dyn ToolCommand + Send>> {
+        let name = args.get("name")?.as_str()?.to_string();
+        Some(Box::new(SaveCommand { name }))
+    }
 }
 
-fn command_specs() -> Vec<Box<dyn CommandSpec>> {
+struct LoadSpec;
+impl CommandSpec for LoadSpec {
+    fn name(&self) -> &'static str {
+        "load"
+    }
+    fn parse(&self, args: &str) -> Option<Box<dyn ToolCommand + Send>> {
+        let name = args.split_whitespace().next()?;
+        Some(Box::new(LoadCommand {
+            name: name.to_string(),
+        }))
+    }
+    fn tool_schema(&self) -> Value {
+        json!({
+            "type": "function",
+            "function": {
+                "name": "load",
+                "description": "加载一个之前保存的命名会话，覆盖当前对话",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string", "description": "会话名称" }
+                    },
+                    "required": ["name"]
+                }
+            }
+        })
+    }
+    fn from_tool_args(&self, args: &Value) -> Option<Box<dyn ToolCommand + Send>> {
+        let name = args.get("name")?.as_str()?.to_string();
+        Some(Box::new(LoadCommand { name }))
+    }
+}
+
+struct RunSpec;
+impl CommandSpec for RunSpec {
+    fn name(&self) -> &'static str {
+        "run"
+    }
+    fn parse(&self, args: &str) -> Option<Box<dyn ToolCommand + Send>> {
+        if args.trim().is_empty() {
+            return None;
+        }
+        Some(Box::new(RunCommand {
+            cmd: args.to_string(),
+        }))
+    }
+    fn tool_schema(&self) -> Value {
+        json!({
+            "type": "function",
+            "function": {
+                "name": "run",
+                "description": "在 shell 中执行命令，stdout/stderr 按行实时流式返回",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "cmd": { "type": "string", "description": "要执行的 shell 命令" }
+                    },
+                    "required": ["cmd"]
+                }
+            }
+        })
+    }
+    fn from_tool_args(&self, args: &Value) -> Option<Box<dyn ToolCommand + Send>> {
+        let cmd = args.get("cmd")?.as_str()?.to_string();
+        Some(Box::new(RunCommand { cmd }))
+    }
+    fn model_callable(&self) -> bool {
+        // 任意 shell 执行无法套用 PendingEdit/PendingConfirmation 的单文件确认模型，
+        // 且不应暴露给自动工具调用循环；只保留给人类显式输入的 `:run`。
+        false
+    }
+}
+
+struct UndoSpec;
+impl CommandSpec for UndoSpec {
+    fn name(&self) -> &'static str {
+        "undo"
+    }
+    fn parse(&self, _args: &str) -> Option<Box<dyn ToolCommand + Send>> {
+        Some(Box::new(UndoCommand))
+    }
+    fn tool_schema(&self) -> Value {
+        json!({
+            "type": "function",
+            "function": {
+                "name": "undo",
+                "description": "撤销最近一次文件变更",
+                "parameters": { "type": "object", "properties": {} }
+            }
+        })
+    }
+    fn from_tool_args(&self, _args: &Value) -> Option<Box<dyn ToolCommand + Send>> {
+        Some(Box::new(UndoCommand))
+    }
+}
+
+struct RedoSpec;
+impl CommandSpec for RedoSpec {
+    fn name(&self) -> &'static str {
+        "redo"
+    }
+    fn parse(&self, _args: &str) -> Option<Box<dyn ToolCommand + Send>> {
+        Some(Box::new(RedoCommand))
+    }
+    fn tool_schema(&self) -> Value {
+        json!({
+            "type": "function",
+            "function": {
+                "name": "redo",
+                "description": "重做最近一次被撤销的文件变更",
+                "parameters": { "type": "object", "properties": {} }
+            }
+        })
+    }
+    fn from_tool_args(&self, _args: &Value) -> Option<Box<dyn ToolCommand + Send>> {
+        Some(Box::new(RedoCommand))
+    }
+}
+
+struct EarlierSpec;
+impl CommandSpec for EarlierSpec {
+    fn name(&self) -> &'static str {
+        "earlier"
+    }
+    fn parse(&self, args: &str) -> Option<Box<dyn ToolCommand + Send>> {
+        let kind = crate::history::parse_undo_kind(args.trim())?;
+        Some(Box::new(EarlierCommand { kind }))
+    }
+    fn tool_schema(&self) -> Value {
+        json!({
+            "type": "function",
+            "function": {
+                "name": "earlier",
+                "description": "按变更次数（如 \"3c\"）或时间跨度（如 \"5m\"）回溯历史",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "amount": { "type": "string", "description": "形如 5m（5 分钟）或 3c（3 次变更）" }
+                    },
+                    "required": ["amount"]
+                }
+            }
+        })
+    }
+    fn from_tool_args(&self, args: &Value) -> Option<Box<dyn ToolCommand + Send>> {
+        let amount = args.get("amount")?.as_str()?;
+        let kind = crate::history::parse_undo_kind(amount)?;
+        Some(Box::new(EarlierCommand { kind }))
+    }
+}
+
+struct LaterSpec;
+impl CommandSpec for LaterSpec {
+    fn name(&self) -> &'static str {
+        "later"
+    }
+    fn parse(&self, args: &str) -> Option<Box<dyn ToolCommand + Send>> {
+        let kind = crate::history::parse_undo_kind(args.trim())?;
+        Some(Box::new(LaterCommand { kind }))
+    }
+    fn tool_schema(&self) -> Value {
+        json!({
+            "type": "function",
+            "function": {
+                "name": "later",
+                "description": "按变更次数（如 \"3c\"）或时间跨度（如 \"5m\"）前进历史",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "amount": { "type": "string", "description": "形如 5m（5 分钟）或 3c（3 次变更）" }
+                    },
+                    "required": ["amount"]
+                }
+            }
+        })
+    }
+    fn from_tool_args(&self, args: &Value) -> Option<Box<dyn ToolCommand + Send>> {
+        let amount = args.get("amount")?.as_str()?;
+        let kind = crate::history::parse_undo_kind(amount)?;
+        Some(Box::new(LaterCommand { kind }))
+    }
+}
+
+pub fn command_specs() -> Vec<Box<dyn CommandSpec>> {
     vec![
         Box::new(TouchSpec),
         Box::new(RmSpec),
@@ -295,10 +1040,39 @@ fn command_specs() -> Vec<Box<dyn CommandSpec>> {
         Box::new(FindSpec),
         Box::new(EditAtSpec),
         Box::new(MoveContentSpec),
+        Box::new(SearchSpec),
+        Box::new(SaveSpec),
+        Box::new(LoadSpec),
+        Box::new(RunSpec),
+        Box::new(UndoSpec),
+        Box::new(RedoSpec),
+        Box::new(EarlierSpec),
+        Box::new(LaterSpec),
     ]
 }
 
-pub fn parse_command(input: &str) -> Option<Box<dyn ToolCommand + Send>> {
+/// 收集所有内置命令的 OpenAI 工具调用 schema，供请求携带。
+pub fn tool_schemas() -> Vec<Value> {
+    command_specs()
+        .iter()
+        .filter(|s| s.model_callable())
+        .map(|s| s.tool_schema())
+        .collect()
+}
+
+/// 根据模型返回的工具名与 JSON 参数字符串构造对应的命令；不可由模型调用的命令（如 `run`）一律拒绝。
+pub fn command_from_tool_call(name: &str, args_json: &str) -> Option<Box<dyn ToolCommand + Send>> {
+    let args: Value = serde_json::from_str(args_json).ok()?;
+    for spec in command_specs() {
+        if spec.name() == name && spec.model_callable() {
+            return spec.from_tool_args(&args);
+        }
+    }
+    None
+}
+
+/// 解析形如 `:find foo` 的命令行输入，返回命令名（用于折叠占位符的标签）及命令本身。
+pub fn parse_command(input: &str) -> Option<(String, Box<dyn ToolCommand + Send>)> {
     let trimmed = input.trim();
     if !trimmed.starts_with(':') {
         return None;
@@ -311,19 +1085,24 @@ pub fn parse_command(input: &str) -> Option<Box<dyn ToolCommand + Send>> {
         if spec.name() == cmd
             && let Some(c) = spec.parse(args)
         {
-            return Some(c);
+            return Some((cmd.to_string(), c));
         }
     }
     None
 }
 
-pub async fn run_command(cmd: Box<dyn ToolCommand + Send>, tx: UnboundedSender<AppEvent>) {
+pub async fn run_command(name: String, cmd: Box<dyn ToolCommand + Send>, tx: UnboundedSender<AppEvent>) {
     tx.send(AppEvent::Status(Status::Requesting)).ok();
-    let result = cmd.execute().await;
+    let result = cmd.execute(tx.clone()).await;
 
     match result {
-        Ok(msg) => {
-            tx.send(AppEvent::System(msg)).ok();
+        Ok(body) => {
+            tx.send(AppEvent::CommandOutput {
+                label: name,
+                body,
+                folded: true,
+            })
+            .ok();
             tx.send(AppEvent::Status(Status::Idle)).ok();
         }
         Err(e) => {
@@ -332,3 +1111,57 @@ pub async fn run_command(cmd: Box<dyn ToolCommand + Send>, tx: UnboundedSender<A
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_line_ending_picks_majority_style() {
+        assert_eq!(detect_line_ending("a\r\nb\r\nc\r\n"), "\r\n");
+        assert_eq!(detect_line_ending("a\nb\nc\n"), "\n");
+        assert_eq!(detect_line_ending("no newlines here"), "\n");
+    }
+
+    #[test]
+    fn line_start_offset_locates_requested_line() {
+        let text = "first\nsecond\nthird";
+        assert_eq!(line_start_offset(text, 1, "\n"), Some((0, "first")));
+        assert_eq!(line_start_offset(text, 2, "\n"), Some((6, "second")));
+        assert_eq!(line_start_offset(text, 4, "\n"), None);
+    }
+
+    #[test]
+    fn char_col_to_byte_handles_multibyte_and_out_of_range_columns() {
+        let line = "héllo";
+        // 'h'(1 byte) + 'é'(2 bytes) => byte offset 3 for the 3rd character ('l').
+        assert_eq!(char_col_to_byte(line, 2), 3);
+        // 越界列号钳制到行尾字节长度，不会落在字符中间。
+        assert_eq!(char_col_to_byte(line, 100), line.len());
+    }
+
+    #[test]
+    fn char_pos_to_byte_offset_clamps_out_of_range_line() {
+        let text = "a\nbb\nccc";
+        assert_eq!(char_pos_to_byte_offset(text, 2, 1, "\n"), 3);
+        assert_eq!(char_pos_to_byte_offset(text, 99, 0, "\n"), text.len());
+    }
+
+    #[test]
+    fn next_token_skips_irregular_whitespace_between_tokens() {
+        let (first, rest) = next_token("foo.rs  3  0  hello world").unwrap();
+        assert_eq!(first, "foo.rs");
+        let (second, rest) = next_token(rest).unwrap();
+        assert_eq!(second, "3");
+        let (third, rest) = next_token(rest).unwrap();
+        assert_eq!(third, "0");
+        // next_token 本身只负责跳 token，不吞掉调用方想保留的额外缩进空白。
+        assert_eq!(rest, "  hello world");
+    }
+
+    #[test]
+    fn next_token_returns_none_on_empty_input() {
+        assert!(next_token("").is_none());
+        assert!(next_token("   ").is_none());
+    }
+}
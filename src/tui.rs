@@ -1,25 +1,142 @@
+use std::sync::Arc;
+
 use ratatui::prelude::*;
-use ratatui::widgets::{Block, Borders, Paragraph};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph};
+use serde::{Deserialize, Serialize};
+use tokio::process::Child;
+use tokio::sync::Mutex;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
 
+use crate::diff::{DiffOp, PendingConfirmation, PendingEdit};
+use crate::history::{History, Transaction, UndoKind};
+use crate::providers::{self, CompletionProvider};
+
+/// Tab 触发的路径补全候选列表及当前高亮项。
+pub struct PathPicker {
+    pub candidates: Vec<String>,
+    pub selected: usize,
+}
+
+/// 折叠判定：超过这么多行的 system/tool 消息默认折叠为摘要行。
+const COLLAPSE_LINE_THRESHOLD: usize = 8;
+
+/// 一条对话消息。`system`/`tool` 角色的长输出默认折叠，仅展示 `summary`。
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+    pub collapsed: bool,
+    pub summary: Option<String>,
+}
+
+impl ChatMessage {
+    pub fn new(role: impl Into<String>, content: impl Into<String>) -> Self {
+        let role = role.into();
+        let content = content.into();
+        let line_count = content.lines().count();
+        let should_collapse =
+            matches!(role.as_str(), "system" | "tool") && line_count > COLLAPSE_LINE_THRESHOLD;
+        let summary = if should_collapse {
+            let first_line = content.lines().next().unwrap_or("");
+            Some(format!(
+                "▸ {}: {} …（共 {} 行，Ctrl+T 展开/折叠）",
+                role, first_line, line_count
+            ))
+        } else {
+            None
+        };
+        Self {
+            role,
+            content,
+            collapsed: should_collapse,
+            summary,
+        }
+    }
+}
+
+/// 把 `run_command` 的结果包装成一条可折叠的 `tool` 消息："▶ {label} — N 行"。
+pub fn fold_command_output(label: String, body: String, folded: bool) -> ChatMessage {
+    let line_count = body.lines().count();
+    ChatMessage {
+        role: "tool".to_string(),
+        content: body,
+        collapsed: folded,
+        summary: Some(format!(
+            "▶ {} — {} 行（Ctrl+T 展开/折叠）",
+            label, line_count
+        )),
+    }
+}
+
 pub enum Status {
     Idle,
     Requesting,
+    /// 通过 `CompletionProvider` 流式接收 token 的过程中。
+    Streaming,
+    /// `:search` 正在增量重建嵌入索引。
+    Indexing,
+    /// `:search` 索引就绪，正在计算相似度排序。
+    Searching,
     Error(String),
 }
 
 pub enum AppEvent {
     Status(Status),
     System(String),
+    Edit(PendingEdit),
+    /// 请求把当前对话保存为命名会话（实际写盘由持有 `App` 的主循环完成）。
+    SaveRequested(String),
+    /// 请求加载某个命名会话，覆盖当前对话。
+    LoadRequested(String),
+    /// `:run` 启动的子进程句柄，供主循环在 Esc/Ctrl+C 时终止。
+    ProcessStarted(Arc<Mutex<Child>>),
+    /// `:run` 子进程已退出（或被终止），携带退出码以清除 `running_child`。
+    ProcessFinished(Option<i32>),
+    /// 命令已自行完成磁盘变更，请求记录为一次历史修订。
+    HistoryRecord(Transaction),
+    /// `rm`/`move-content` 等破坏性命令请求用户确认后才真正执行（Ctrl+Y 应用 / Ctrl+N 丢弃）。
+    ConfirmRequested(PendingConfirmation),
+    /// 请求撤销最近一次修订。
+    UndoRequested,
+    /// 请求重做最近一次被撤销的修订。
+    RedoRequested,
+    /// 按步数或时间跨度连续撤销（`:earlier 5m` / `:earlier 2c`）。
+    EarlierRequested(UndoKind),
+    /// 按步数或时间跨度连续重做（`:later 5m` / `:later 2c`）。
+    LaterRequested(UndoKind),
+    /// 非 OpenAI `CompletionProvider` 产出的一个增量文本块。
+    Token(String),
+    /// `run_command` 产出的命令结果，折叠为 "▶ {label} — N 行" 占位符（Ctrl+T 展开/折叠）。
+    CommandOutput {
+        label: String,
+        body: String,
+        folded: bool,
+    },
 }
 
 pub struct App {
     pub input: String,
-    pub messages: Vec<(String, String)>, // (role, content)
+    pub messages: Vec<ChatMessage>,
     pub model: String,
     pub status: Status,
     pub events_tx: UnboundedSender<AppEvent>,
     pub events_rx: UnboundedReceiver<AppEvent>,
+    /// 等待用户按 Ctrl+Y/Ctrl+N 确认或丢弃的写入/编辑，按生成顺序排列。
+    pub pending_edits: Vec<PendingEdit>,
+    /// 等待用户按 Ctrl+Y/Ctrl+N 确认或丢弃的破坏性操作（`rm`/`move-content`），按生成顺序排列。
+    pub pending_confirmations: Vec<PendingConfirmation>,
+    /// 历史面板的滚动位置；`None` 表示始终跟随最新内容（自动置底）。
+    pub scroll_offset: Option<u16>,
+    /// 当前打开的路径补全弹窗（`Tab` 触发，`Enter` 确认并关闭，`Esc` 取消；其他按键不会关闭）。
+    pub path_picker: Option<PathPicker>,
+    /// Ctrl+Up/Ctrl+Down 移动的消息高亮游标，`None` 表示默认指向最后一条消息。
+    pub selected_message: Option<usize>,
+    /// 正在运行的 `:run` 子进程，`Some` 时 Esc/Ctrl+C 会终止它而不是退出程序。
+    pub running_child: Option<Arc<Mutex<Child>>>,
+    /// 文件变更的撤销/重做历史（Helix 风格修订树）。
+    pub history: History,
+    /// 当前会话使用的模型补全后端，由 `COMPLETION_PROVIDER` 环境变量在启动时选定。
+    pub provider: Box<dyn CompletionProvider>,
 }
 
 pub fn mask_api_key(key: &str) -> String {
@@ -44,12 +161,9 @@ impl App {
         let masked_key = mask_api_key(&api_key);
 
         let messages = vec![
-            (
-                "system".to_string(),
-                "你是一个助理，帮助进行AI编码。".to_string(),
-            ),
-            (
-                "system".to_string(),
+            ChatMessage::new("system", "你是一个助理，帮助进行AI编码。"),
+            ChatMessage::new(
+                "system",
                 format!(
                     "当前配置：api_base={}, model={}, api_key={}",
                     api_base, model, masked_key
@@ -66,6 +180,14 @@ impl App {
             status: Status::Idle,
             events_tx,
             events_rx,
+            pending_edits: Vec::new(),
+            pending_confirmations: Vec::new(),
+            scroll_offset: None,
+            path_picker: None,
+            selected_message: None,
+            running_child: None,
+            history: History::new(),
+            provider: providers::select_provider(),
         }
     }
 }
@@ -84,19 +206,81 @@ pub fn ui(frame: &mut Frame, app: &App) {
         .split(frame.area());
 
     // Render messages
-    let history_text = app
-        .messages
-        .iter()
-        .map(|(role, content)| format!("{}: {}", role, content))
-        .collect::<Vec<_>>()
-        .join("\n");
-    let history = Paragraph::new(history_text);
+    let selected = app
+        .selected_message
+        .unwrap_or(app.messages.len().saturating_sub(1));
+    let mut lines: Vec<Line> = Vec::new();
+    for (i, msg) in app.messages.iter().enumerate() {
+        let marker = if i == selected { "» " } else { "  " };
+        if msg.collapsed {
+            let summary = msg.summary.as_deref().unwrap_or(&msg.content);
+            lines.push(Line::from(Span::styled(
+                format!("{}{}", marker, summary),
+                Style::default().fg(Color::DarkGray),
+            )));
+        } else {
+            let mut first = true;
+            for line in msg.content.lines() {
+                if first {
+                    lines.push(Line::from(format!("{}{}: {}", marker, msg.role, line)));
+                    first = false;
+                } else {
+                    lines.push(Line::from(format!("    {}", line)));
+                }
+            }
+            if first {
+                // 空内容也至少占一行，保持与折叠态一致的可见性
+                lines.push(Line::from(format!("{}{}: ", marker, msg.role)));
+            }
+        }
+    }
+
+    // 待确认的编辑以彩色 diff 形式追加在历史末尾
+    for edit in &app.pending_edits {
+        lines.push(Line::from(Span::styled(
+            format!("▸ 待确认变更: {} (Ctrl+Y 应用 / Ctrl+N 丢弃)", edit.path),
+            Style::default().fg(Color::Yellow),
+        )));
+        for op in &edit.diff {
+            let line = match op {
+                DiffOp::Context(l) => Line::from(Span::raw(format!("  {}", l))),
+                DiffOp::Added(l) => Line::from(Span::styled(
+                    format!("+ {}", l),
+                    Style::default().fg(Color::Green),
+                )),
+                DiffOp::Removed(l) => Line::from(Span::styled(
+                    format!("- {}", l),
+                    Style::default().fg(Color::Red),
+                )),
+            };
+            lines.push(line);
+        }
+    }
+
+    // 待确认的破坏性操作（rm/move-content）以一行摘要追加在历史末尾
+    for action in &app.pending_confirmations {
+        lines.push(Line::from(Span::styled(
+            format!(
+                "▸ 待确认操作: {} (Ctrl+Y 应用 / Ctrl+N 丢弃)",
+                action.describe()
+            ),
+            Style::default().fg(Color::Yellow),
+        )));
+    }
+
+    let max_offset = (lines.len() as u16).saturating_sub(chunks[0].height);
+    let offset = app.scroll_offset.unwrap_or(u16::MAX).min(max_offset);
+
+    let history = Paragraph::new(Text::from(lines)).scroll((offset, 0));
     frame.render_widget(history, chunks[0]);
 
     // Render status（输入框上方，无边框）
     let status_text = match &app.status {
         Status::Idle => "按 Enter 发送，Esc 退出".to_string(),
         Status::Requesting => "正在请求OpenAI...".to_string(),
+        Status::Streaming => format!("正在从 {} 流式接收回复...", app.provider.name()),
+        Status::Indexing => "正在重建语义搜索索引...".to_string(),
+        Status::Searching => "正在计算相似度排序...".to_string(),
         Status::Error(e) => format!("请求失败: {}", e),
     };
     let status = Paragraph::new(status_text);
@@ -109,4 +293,38 @@ pub fn ui(frame: &mut Frame, app: &App) {
             .borders(Borders::ALL),
     );
     frame.render_widget(input, chunks[2]);
+
+    // Tab 触发的路径补全弹窗，悬浮在输入框上方
+    if let Some(picker) = &app.path_picker {
+        let popup_height = (picker.candidates.len().min(8) as u16) + 2;
+        let area = Rect {
+            x: chunks[2].x,
+            y: chunks[2].y.saturating_sub(popup_height),
+            width: chunks[2].width,
+            height: popup_height,
+        };
+        let items: Vec<Line> = picker
+            .candidates
+            .iter()
+            .take(8)
+            .enumerate()
+            .map(|(i, c)| {
+                if i == picker.selected {
+                    Line::from(Span::styled(
+                        format!("> {}", c),
+                        Style::default().fg(Color::Black).bg(Color::Cyan),
+                    ))
+                } else {
+                    Line::from(format!("  {}", c))
+                }
+            })
+            .collect();
+        frame.render_widget(Clear, area);
+        let popup = Paragraph::new(Text::from(items)).block(
+            Block::default()
+                .title("路径补全 (Tab/Shift+Tab 切换, Enter 选择)")
+                .borders(Borders::ALL),
+        );
+        frame.render_widget(popup, area);
+    }
 }